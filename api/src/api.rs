@@ -1,15 +1,19 @@
-use crate::{db, devices, program, AppError, Result};
-use axum::extract::State;
-use axum::http::header::CONTENT_TYPE;
+use crate::{config, db, devices, events, export, program, validation, AppError, Result};
+use axum::extract::{Query, State};
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
 use axum::http::{Method, StatusCode};
+use axum::response::sse::{self, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::{Json, Router};
 use axum_extra::headers::HeaderMap;
 use axum_extra::routing::{RouterExt, TypedPath};
+use futures::{Stream, StreamExt};
 use http::HeaderValue;
 use include_dir::{include_dir, Dir};
 use serde::Deserialize;
+use std::num::NonZeroU32;
 use tokio::sync::oneshot;
+use tokio_stream::wrappers::BroadcastStream;
 use tower::ServiceBuilder;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
@@ -24,6 +28,10 @@ pub struct AppState {
     db: db::Database,
     device_tx: devices::Sender,
     brew_tx: program::Sender,
+    events_tx: events::Sender,
+    limits: config::Limits,
+    /// Target temperature set to turn the heater off.
+    safe_temperature: f32,
 }
 
 impl AppState {
@@ -34,22 +42,40 @@ impl AppState {
         db: db::Database,
         device_tx: devices::Sender,
         brew_tx: program::Sender,
+        events_tx: events::Sender,
+        limits: config::Limits,
+        safe_temperature: f32,
     ) -> Result<Self> {
         Ok(Self {
             db,
             device_tx,
             brew_tx,
+            events_tx,
+            limits,
+            safe_temperature,
         })
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Error: {}", self),
-        )
-            .into_response()
+        if let AppError::Validation(errors) = self {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response();
+        }
+
+        let status = match &self {
+            AppError::SqlError(sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            AppError::DeviceDisconnected => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            AppError::BrewOngoing
+            | AppError::BrewNotRunning(_)
+            | AppError::BrewNotInterrupted(_)
+            | AppError::NotAwaitingConfirmation(_)
+            | AppError::RecipeInUse(_) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, format!("Error: {}", self)).into_response()
     }
 }
 
@@ -58,12 +84,21 @@ fn insert_header_from_extension(map: &mut HeaderMap, ext: &str) {
         "css" => {
             map.insert(CONTENT_TYPE, HeaderValue::from_static("text/css"));
         }
+        "csv" => {
+            map.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/csv; charset=utf-8"),
+            );
+        }
         "html" => {
             map.insert(
                 CONTENT_TYPE,
                 HeaderValue::from_static("text/html; charset=utf-8"),
             );
         }
+        "json" => {
+            map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
         "js" => {
             map.insert(
                 CONTENT_TYPE,
@@ -89,6 +124,140 @@ async fn get_state(_: StateRoute, State(state): State<AppState>) -> Result<Json<
     Ok(Json(rx.await??))
 }
 
+/// Get the status of the running brew or `None`.
+async fn brew_status(state: &AppState) -> Result<Option<models::BrewStatus>> {
+    let (resp, rx) = oneshot::channel();
+    let _ = state.brew_tx.send(program::Command::Status { resp }).await;
+    rx.await?
+}
+
+/// Refuse manual device control while a brew is running unless `force` is set.
+async fn ensure_manual_control(state: &AppState, force: bool) -> Result<()> {
+    if !force && brew_status(state).await?.is_some() {
+        warn!("Refusing manual control during brew");
+        return Err(AppError::BrewOngoing);
+    }
+
+    Ok(())
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/target-temperature")]
+struct TargetTemperatureRoute;
+
+#[instrument(skip(state))]
+async fn post_target_temperature(
+    _: TargetTemperatureRoute,
+    State(state): State<AppState>,
+    Json(payload): Json<models::TargetTemperature>,
+) -> Result<()> {
+    ensure_manual_control(&state, payload.force).await?;
+
+    let temperature = payload.target_temperature;
+
+    validation::target_temperature(temperature, &state.limits)?;
+
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::SetTemperature { temperature, resp };
+    let _ = state.device_tx.send(command).await;
+    rx.await?
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/heater-off")]
+struct HeaterOffRoute;
+
+/// Turn the heater off by setting the configured safe temperature.
+#[instrument(skip(state))]
+async fn post_heater_off(
+    _: HeaterOffRoute,
+    State(state): State<AppState>,
+    Json(payload): Json<models::HeaterOff>,
+) -> Result<()> {
+    ensure_manual_control(&state, payload.force).await?;
+
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::SetTemperature {
+        temperature: state.safe_temperature,
+        resp,
+    };
+    let _ = state.device_tx.send(command).await;
+    rx.await?
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/stirrer")]
+struct StirrerRoute;
+
+#[instrument(skip(state))]
+async fn post_stirrer(
+    _: StirrerRoute,
+    State(state): State<AppState>,
+    Json(payload): Json<models::Stirrer>,
+) -> Result<()> {
+    ensure_manual_control(&state, payload.force).await?;
+
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::SetStirrer {
+        on: payload.stirrer_on,
+        resp,
+    };
+    let _ = state.device_tx.send(command).await;
+    rx.await?
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/device/health")]
+struct DeviceHealthRoute;
+
+#[instrument]
+async fn get_device_health(
+    _: DeviceHealthRoute,
+    State(state): State<AppState>,
+) -> Result<Json<models::DeviceHealth>> {
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::Health { resp };
+    let _ = state.device_tx.send(command).await;
+    Ok(Json(rx.await??))
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/device/info")]
+struct DeviceInfoRoute;
+
+#[instrument]
+async fn get_device_info(
+    _: DeviceInfoRoute,
+    State(state): State<AppState>,
+) -> Result<Json<models::DeviceInfo>> {
+    Ok(Json(device_info(&state).await?))
+}
+
+/// Query firmware identification and capabilities from the device task.
+async fn device_info(state: &AppState) -> Result<models::DeviceInfo> {
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::Info { resp };
+    let _ = state.device_tx.send(command).await;
+    rx.await?
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/events")]
+struct EventsRoute;
+
+#[instrument(skip_all)]
+async fn get_events(
+    _: EventsRoute,
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<sse::Event, axum::Error>>> {
+    // Lagging subscribers miss a few events but keep the stream open.
+    let stream = BroadcastStream::new(state.events_tx.subscribe())
+        .filter_map(|event| async move { event.ok() })
+        .map(|event| sse::Event::default().json_data(event));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
 #[derive(TypedPath, Deserialize)]
 #[typed_path("/api/recipes")]
 struct RecipesRoute;
@@ -126,10 +295,35 @@ async fn post_recipe(
 ) -> Result<Json<models::NewRecipeResponse>> {
     debug!("Storing {:?}", payload);
 
+    validation::recipe(&payload, &state.limits)?;
+
     let result = state.db.add_recipe(payload).await?;
     Ok(Json(result))
 }
 
+#[instrument(skip_all)]
+async fn put_recipe(
+    RecipeRoute { id }: RecipeRoute,
+    State(state): State<AppState>,
+    Json(payload): Json<models::NewRecipe>,
+) -> Result<()> {
+    debug!("Updating {} with {:?}", id, payload);
+
+    validation::recipe(&payload, &state.limits)?;
+
+    state.db.update_recipe(id, payload).await
+}
+
+#[instrument(skip_all)]
+async fn delete_recipe(
+    RecipeRoute { id }: RecipeRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Deleting {}", id);
+
+    state.db.delete_recipe(id).await
+}
+
 #[derive(TypedPath)]
 #[typed_path("/api/brews")]
 struct BrewsRoute;
@@ -142,20 +336,253 @@ async fn start_brew(
 ) -> Result<()> {
     debug!("Start brew");
 
+    if !device_info(&state).await?.heater {
+        return Err(AppError::Unsupported("heater control"));
+    }
+
     let recipe = state.db.recipe(payload.id).await?;
-    let result = state.db.add_brew(recipe.id).await?;
     let (resp, rx) = oneshot::channel();
+    let command = program::Command::Start { recipe, resp };
+    let _ = state.brew_tx.send(command).await;
+    rx.await??;
 
-    let command = program::Command::Start {
-        id: result.id,
-        steps: recipe.steps,
-        resp,
-    };
+    Ok(())
+}
 
-    let _ = state.brew_tx.send(command).await;
+#[instrument(skip_all)]
+async fn get_brews(_: BrewsRoute, State(state): State<AppState>) -> Result<Json<models::Brews>> {
+    Ok(Json(state.db.brews().await?))
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id")]
+struct BrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip_all)]
+async fn get_brew(
+    BrewRoute { id }: BrewRoute,
+    State(state): State<AppState>,
+) -> Result<Json<models::Brew>> {
+    Ok(Json(state.db.brew(id).await?))
+}
+
+/// Send a control `command` built from a responder to the program channel and wait for the result.
+async fn send_brew_command<F>(state: &AppState, command: F) -> Result<()>
+where
+    F: FnOnce(oneshot::Sender<Result<()>>) -> program::Command,
+{
+    let (resp, rx) = oneshot::channel();
+    let _ = state.brew_tx.send(command(resp)).await;
     rx.await?
 }
 
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/samples")]
+struct SamplesRoute {
+    id: models::BrewId,
+}
+
+/// Optional time range and bucket size in seconds to downsample long brews.
+#[derive(Debug, Deserialize)]
+struct SamplesQuery {
+    since: Option<i64>,
+    until: Option<i64>,
+    bucket: Option<NonZeroU32>,
+}
+
+#[instrument(skip(state))]
+async fn get_samples(
+    SamplesRoute { id }: SamplesRoute,
+    Query(query): Query<SamplesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<models::Samples>> {
+    let brew = state.db.brew(id).await?;
+    let bucket = query.bucket.unwrap_or(NonZeroU32::MIN);
+
+    let samples = state
+        .db
+        .samples(brew.id, query.since, query.until, bucket)
+        .await?;
+
+    Ok(Json(samples))
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/export")]
+struct ExportRoute {
+    id: models::BrewId,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[instrument(skip(state))]
+async fn get_export(
+    ExportRoute { id }: ExportRoute,
+    Query(query): Query<ExportQuery>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let log = state.db.brew_log(id).await?;
+    let mut headers = HeaderMap::new();
+
+    let (extension, body) = match query.format {
+        ExportFormat::Json => {
+            insert_header_from_extension(&mut headers, "json");
+            ("json", serde_json::to_vec_pretty(&log)?)
+        }
+        ExportFormat::Csv => {
+            insert_header_from_extension(&mut headers, "csv");
+            ("csv", export::to_csv(&log).into_bytes())
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"brew-{id}.{extension}\"");
+    headers.insert(CONTENT_DISPOSITION, HeaderValue::from_str(&disposition)?);
+
+    Ok((headers, body).into_response())
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/current")]
+struct CurrentBrewRoute;
+
+#[instrument(skip(state))]
+async fn get_current_brew(
+    _: CurrentBrewRoute,
+    State(state): State<AppState>,
+) -> Result<Json<Option<models::BrewStatus>>> {
+    Ok(Json(brew_status(&state).await?))
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/pause")]
+struct PauseBrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip(state))]
+async fn pause_brew(
+    PauseBrewRoute { id }: PauseBrewRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Pause brew");
+    send_brew_command(&state, |resp| program::Command::Pause { id, resp }).await
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/resume")]
+struct ResumeBrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip(state))]
+async fn resume_brew(
+    ResumeBrewRoute { id }: ResumeBrewRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Resume brew");
+    send_brew_command(&state, |resp| program::Command::Resume { id, resp }).await
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/abort")]
+struct AbortBrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip(state))]
+async fn abort_brew(
+    AbortBrewRoute { id }: AbortBrewRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Abort brew");
+    send_brew_command(&state, |resp| program::Command::Abort { id, resp }).await
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/confirm")]
+struct ConfirmBrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip(state))]
+async fn confirm_brew(
+    ConfirmBrewRoute { id }: ConfirmBrewRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Confirm brew step");
+    send_brew_command(&state, |resp| program::Command::Confirm { id, resp }).await
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/steps/:step/additions/:addition/acknowledge")]
+struct AcknowledgeAdditionRoute {
+    id: models::BrewId,
+    step: usize,
+    addition: usize,
+}
+
+#[instrument(skip(state))]
+async fn acknowledge_addition(
+    AcknowledgeAdditionRoute { id, step, addition }: AcknowledgeAdditionRoute,
+    State(state): State<AppState>,
+) -> Result<()> {
+    debug!("Acknowledge addition");
+    state.db.acknowledge_addition(id, step, addition).await
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/interrupted")]
+struct InterruptedBrewsRoute;
+
+#[instrument(skip_all)]
+async fn get_interrupted_brews(
+    _: InterruptedBrewsRoute,
+    State(state): State<AppState>,
+) -> Result<Json<models::Brews>> {
+    Ok(Json(
+        state
+            .db
+            .brews_in_state(models::BrewState::Interrupted)
+            .await?,
+    ))
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/api/brews/:id/recover")]
+struct RecoverBrewRoute {
+    id: models::BrewId,
+}
+
+#[instrument(skip(state))]
+async fn recover_brew(
+    RecoverBrewRoute { id }: RecoverBrewRoute,
+    State(state): State<AppState>,
+    Json(recovery): Json<models::Recovery>,
+) -> Result<()> {
+    debug!("Recover brew");
+
+    send_brew_command(&state, |resp| program::Command::Recover {
+        id,
+        action: recovery.action,
+        resp,
+    })
+    .await
+}
+
 #[derive(TypedPath, Deserialize)]
 #[typed_path("/:path")]
 struct StaticFileRoute {
@@ -199,7 +626,7 @@ pub async fn run(state: AppState) -> Result<()> {
     // Only useful if we run the app via `trunk serve`, if not we serve the static files directly.
     let cors = CorsLayer::new()
         .allow_origin("http://0.0.0.0:8080".parse::<HeaderValue>()?)
-        .allow_methods(vec![Method::GET, Method::POST]);
+        .allow_methods(vec![Method::GET, Method::POST, Method::PUT, Method::DELETE]);
 
     let trace = TraceLayer::new_for_http();
 
@@ -209,10 +636,30 @@ pub async fn run(state: AppState) -> Result<()> {
         .typed_get(get_index)
         .typed_get(get_static)
         .typed_post(start_brew)
+        .typed_get(get_brews)
+        .typed_get(get_brew)
+        .typed_get(get_samples)
+        .typed_get(get_export)
+        .typed_get(get_current_brew)
+        .typed_post(pause_brew)
+        .typed_post(resume_brew)
+        .typed_post(abort_brew)
+        .typed_post(confirm_brew)
+        .typed_post(acknowledge_addition)
+        .typed_get(get_interrupted_brews)
+        .typed_post(recover_brew)
         .typed_get(get_recipes)
         .typed_post(post_recipe)
         .typed_get(get_recipe)
+        .typed_put(put_recipe)
+        .typed_delete(delete_recipe)
         .typed_get(get_state)
+        .typed_post(post_heater_off)
+        .typed_post(post_stirrer)
+        .typed_post(post_target_temperature)
+        .typed_get(get_events)
+        .typed_get(get_device_health)
+        .typed_get(get_device_info)
         .with_state(state)
         .layer(
             ServiceBuilder::new()
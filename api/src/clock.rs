@@ -0,0 +1,163 @@
+//! Time source shared by program execution, database timestamps and the mock device.
+//!
+//! Clock time advances with the tokio clock, so pausing and advancing tokio time drives the device
+//! code deterministically. The database pool times out on a paused tokio clock though, tests going
+//! through the database advance a manual clock instead. A speedup factor lets simulated brews run
+//! faster than wall clock time.
+
+use crate::Result;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(test)]
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::warn;
+
+#[derive(Debug)]
+struct Inner {
+    /// Tokio instant at which the clock was created.
+    origin: Instant,
+    /// Time since the Unix epoch at `origin`.
+    epoch: Duration,
+    /// Factor by which clock time runs faster than tokio time.
+    speedup: f32,
+    /// Time source replacing the tokio clock.
+    #[cfg(test)]
+    manual: Option<Manual>,
+}
+
+/// Clock time advanced by the test to the deadline of the next sleep.
+#[cfg(test)]
+#[derive(Debug)]
+struct Manual {
+    now: watch::Sender<Duration>,
+    /// Deadlines of the pending sleeps.
+    deadlines: watch::Sender<Vec<Duration>>,
+}
+
+/// Pending sleep on a manual clock, removing its deadline when done or dropped.
+#[cfg(test)]
+struct Sleeper<'a> {
+    manual: &'a Manual,
+    deadline: Duration,
+}
+
+#[cfg(test)]
+impl Drop for Sleeper<'_> {
+    fn drop(&mut self) {
+        self.manual.deadlines.send_modify(|deadlines| {
+            if let Some(index) = deadlines.iter().position(|d| *d == self.deadline) {
+                deadlines.swap_remove(index);
+            }
+        });
+    }
+}
+
+/// Cheaply cloneable clock.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    inner: Arc<Inner>,
+}
+
+impl Clock {
+    /// Create a clock starting at the current system time running `speedup` times faster than
+    /// wall clock time. Non-positive factors are replaced by one.
+    pub fn new(speedup: f32) -> Result<Self> {
+        let speedup = if speedup > 0.0 {
+            speedup
+        } else {
+            warn!("Ignoring invalid speedup {speedup}");
+            1.0
+        };
+
+        Ok(Self::starting_at(
+            SystemTime::now().duration_since(UNIX_EPOCH)?,
+            speedup,
+        ))
+    }
+
+    /// Create a clock starting at `epoch` since the Unix epoch.
+    pub fn starting_at(epoch: Duration, speedup: f32) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                origin: Instant::now(),
+                epoch,
+                speedup,
+                #[cfg(test)]
+                manual: None,
+            }),
+        }
+    }
+
+    /// Create a clock starting at `epoch` that stands still until [`Clock::advance`] is called.
+    #[cfg(test)]
+    pub fn manual(epoch: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                origin: Instant::now(),
+                epoch,
+                speedup: 1.0,
+                manual: Some(Manual {
+                    now: watch::channel(Duration::ZERO).0,
+                    deadlines: watch::channel(Vec::new()).0,
+                }),
+            }),
+        }
+    }
+
+    /// Wait until a task sleeps on the manual clock, advance the clock to the earliest deadline
+    /// and wait until the sleeps due by then returned.
+    #[cfg(test)]
+    pub async fn advance(&self) {
+        let manual = self.inner.manual.as_ref().expect("clock is not manual");
+        let mut deadlines = manual.deadlines.subscribe();
+
+        let next = deadlines
+            .wait_for(|deadlines| !deadlines.is_empty())
+            .await
+            .map(|deadlines| deadlines.iter().copied().min().unwrap_or_default())
+            .unwrap();
+
+        manual.now.send_modify(|now| *now = (*now).max(next));
+
+        let _ = deadlines
+            .wait_for(|deadlines| deadlines.iter().all(|deadline| *deadline > next))
+            .await;
+    }
+
+    /// Clock time passed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(test)]
+        if let Some(manual) = &self.inner.manual {
+            return *manual.now.borrow();
+        }
+
+        self.inner.origin.elapsed().mul_f32(self.inner.speedup)
+    }
+
+    /// Current clock time in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> i64 {
+        (self.inner.epoch + self.elapsed()).as_secs() as i64
+    }
+
+    /// Sleep for `duration` of clock time.
+    pub async fn sleep(&self, duration: Duration) {
+        #[cfg(test)]
+        if let Some(manual) = &self.inner.manual {
+            let deadline = self.elapsed() + duration;
+            let _sleeper = Sleeper { manual, deadline };
+            manual
+                .deadlines
+                .send_modify(|deadlines| deadlines.push(deadline));
+
+            let _ = manual
+                .now
+                .subscribe()
+                .wait_for(|now| *now >= deadline)
+                .await;
+            return;
+        }
+
+        sleep(duration.div_f32(self.inner.speedup)).await;
+    }
+}
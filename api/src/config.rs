@@ -4,12 +4,227 @@ use std::path::PathBuf;
 
 const DEFAULT_DEVICE_PATH: &str = "/dev/ttyACM0";
 
+/// Limits recipes are validated against before they are stored.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Lowest allowed step temperature in degree Celsius.
+    pub min_temperature: f32,
+    /// Highest allowed step temperature in degree Celsius.
+    pub max_temperature: f32,
+    /// Maximum number of steps of a single recipe.
+    pub max_steps: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            min_temperature: 0.0,
+            max_temperature: 100.0,
+            max_steps: 32,
+        }
+    }
+}
+
+/// Limits enforced while a brew is running.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Safety {
+    /// Highest tolerated kettle temperature in degree Celsius.
+    pub max_temperature: f32,
+    /// Number of consecutive readings without temperature after which the brew fails.
+    pub max_missing_readings: u32,
+    /// Highest tolerated temperature rise in Kelvin per minute.
+    pub max_rate_of_rise: f32,
+    /// Seconds after which heating fails if the temperature did not approach the target.
+    pub no_progress_timeout: u64,
+    /// Target temperature set when a brew is aborted or fails.
+    pub safe_temperature: f32,
+}
+
+impl Default for Safety {
+    fn default() -> Self {
+        Self {
+            max_temperature: 105.0,
+            max_missing_readings: 5,
+            max_rate_of_rise: 5.0,
+            no_progress_timeout: 900,
+            safe_temperature: 20.0,
+        }
+    }
+}
+
+/// Default tolerance of step temperatures, steps can override each setting.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Tolerance {
+    /// Allowed deviation from the target temperature in Kelvin.
+    pub band: f32,
+    /// Number of consecutive in-band samples before the hold starts.
+    pub settle_samples: u32,
+    /// Reaction to the temperature leaving the band while holding.
+    pub out_of_band: models::OutOfBand,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            band: 0.5,
+            settle_samples: 1,
+            out_of_band: models::OutOfBand::Flag,
+        }
+    }
+}
+
+impl Tolerance {
+    /// Apply the overrides of a `step`.
+    pub fn with(&self, step: Option<&models::Tolerance>) -> Self {
+        let Some(step) = step else {
+            return self.clone();
+        };
+
+        Self {
+            band: step.band.unwrap_or(self.band),
+            settle_samples: step.settle_samples.unwrap_or(self.settle_samples),
+            out_of_band: step.out_of_band.unwrap_or(self.out_of_band),
+        }
+    }
+}
+
+/// Execution of boil steps.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Boil {
+    /// Temperature in degree Celsius from which the wort is considered boiling, lower than 100 at
+    /// altitude.
+    pub temperature: f32,
+    /// Target temperature set on the device while boiling, above the boiling point so that the
+    /// heater keeps running.
+    pub target: f32,
+}
+
+impl Default for Boil {
+    fn default() -> Self {
+        Self {
+            temperature: 99.0,
+            target: 101.0,
+        }
+    }
+}
+
+/// Who switches the heater to reach the target temperature.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlMode {
+    /// The device thermostat follows the target temperature.
+    #[default]
+    Thermostat,
+    /// The server computes the heater duty with a PID controller, if the device supports it.
+    Pid,
+}
+
+/// Gains and sampling of the host-side PID controller, duty is in the range zero to one.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Pid {
+    /// Duty per Kelvin below the setpoint.
+    pub kp: f32,
+    /// Duty per Kelvin second of accumulated error.
+    pub ki: f32,
+    /// Duty per Kelvin per second of falling temperature.
+    pub kd: f32,
+    /// Seconds between two control steps.
+    pub interval: u64,
+}
+
+impl Default for Pid {
+    fn default() -> Self {
+        Self {
+            kp: 0.2,
+            ki: 0.0007,
+            kd: 0.0,
+            interval: 5,
+        }
+    }
+}
+
+/// Heartbeat sent to the device watchdog.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Watchdog {
+    /// Seconds without heartbeat after which the device turns the heater off, zero disables the
+    /// heartbeat.
+    pub timeout: u64,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self { timeout: 10 }
+    }
+}
+
+/// Parameters of the kettle simulated by the mock device.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Simulation {
+    /// Water volume in litres.
+    pub volume: f32,
+    /// Heater power in watts.
+    pub heater_power: f32,
+    /// Ambient and initial temperature in degree Celsius.
+    pub ambient_temperature: f32,
+    /// Heat lost to the environment in watts per Kelvin above ambient temperature.
+    pub heat_loss: f32,
+    /// Maximum deviation of a reading from the simulated temperature in Kelvin.
+    pub sensor_noise: f32,
+    /// The heater switches on below target minus hysteresis and off at the target temperature.
+    pub hysteresis: f32,
+    /// Time constant of the temperature sensor following the kettle temperature in seconds.
+    pub sensor_lag: f32,
+    /// Factor by which simulated time runs faster than wall clock time. This applies to program
+    /// holds and recorded timestamps as well.
+    pub speedup: f32,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            volume: 20.0,
+            heater_power: 3000.0,
+            ambient_temperature: 20.0,
+            heat_loss: 8.0,
+            sensor_noise: 0.1,
+            hysteresis: 0.5,
+            sensor_lag: 0.0,
+            speedup: 1.0,
+        }
+    }
+}
+
 /// Server configuration.
 pub struct Config {
     /// Path to the brewslave device. By default this is /dev/ttyACM0.
     pub device: PathBuf,
+    /// Protocol spoken by the brewslave firmware.
+    pub protocol: comm::Protocol,
+    /// Heater control of the device.
+    pub control: ControlMode,
     /// Path to the database file or `None`.
     pub database: Option<String>,
+    /// Recipe limits.
+    pub limits: Limits,
+    /// Safety limits of running brews.
+    pub safety: Safety,
+    /// Boil step execution.
+    pub boil: Boil,
+    /// Default step temperature tolerance.
+    pub tolerance: Tolerance,
+    /// Device watchdog heartbeat.
+    pub watchdog: Watchdog,
+    /// Host-side heater control.
+    pub pid: Pid,
+    /// Simulated kettle used with `--use-mock`.
+    pub simulation: Simulation,
 }
 
 #[derive(Deserialize)]
@@ -17,14 +232,41 @@ struct Serialized {
     #[serde(default)]
     device: Option<PathBuf>,
     #[serde(default)]
+    protocol: comm::Protocol,
+    #[serde(default)]
+    control: ControlMode,
+    #[serde(default)]
     database: Option<String>,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(default)]
+    safety: Safety,
+    #[serde(default)]
+    boil: Boil,
+    #[serde(default)]
+    tolerance: Tolerance,
+    #[serde(default)]
+    watchdog: Watchdog,
+    #[serde(default)]
+    pid: Pid,
+    #[serde(default)]
+    simulation: Simulation,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             device: PathBuf::from(DEFAULT_DEVICE_PATH),
+            protocol: comm::Protocol::default(),
+            control: ControlMode::default(),
             database: None,
+            limits: Limits::default(),
+            safety: Safety::default(),
+            boil: Boil::default(),
+            tolerance: Tolerance::default(),
+            watchdog: Watchdog::default(),
+            pid: Pid::default(),
+            simulation: Simulation::default(),
         }
     }
 }
@@ -42,7 +284,16 @@ impl Config {
                 device: config
                     .device
                     .unwrap_or_else(|| PathBuf::from(DEFAULT_DEVICE_PATH)),
+                protocol: config.protocol,
+                control: config.control,
                 database: config.database,
+                limits: config.limits,
+                safety: config.safety,
+                boil: config.boil,
+                tolerance: config.tolerance,
+                watchdog: config.watchdog,
+                pid: config.pid,
+                simulation: config.simulation,
             })
         } else {
             Ok(Self::default())
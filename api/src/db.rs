@@ -1,15 +1,16 @@
+use crate::clock::Clock;
 use crate::{AppError, Result};
-use futures::future::try_join_all;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
-use sqlx::{ConnectOptions, FromRow};
+use sqlx::{ConnectOptions, FromRow, Sqlite, Transaction};
 use std::convert::From;
+use std::num::NonZeroU32;
 use std::str::FromStr;
-use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{info, instrument};
 
 #[derive(Clone, Debug)]
 pub struct Database {
     pool: SqlitePool,
+    clock: Clock,
 }
 
 #[derive(FromRow)]
@@ -19,16 +20,84 @@ struct Recipe {
     pub description: String,
 }
 
+#[derive(sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum StepKind {
+    Hold,
+    Ramp,
+    Boil,
+    Confirm,
+    Reminder,
+}
+
 #[derive(FromRow)]
 pub struct Step {
     pub position: i64,
-    pub target_temperature: f32,
-    pub duration: i64,
+    pub kind: StepKind,
+    pub target_temperature: Option<f32>,
+    pub duration: Option<i64>,
+    pub rate: Option<f32>,
+    pub message: Option<String>,
+    pub stirrer: bool,
+    pub tolerance_band: Option<f32>,
+    pub settle_samples: Option<i64>,
+    pub out_of_band: Option<OutOfBand>,
+}
+
+#[derive(Clone, Copy, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum OutOfBand {
+    Flag,
+    Pause,
+}
+
+impl From<OutOfBand> for models::OutOfBand {
+    fn from(out_of_band: OutOfBand) -> Self {
+        match out_of_band {
+            OutOfBand::Flag => Self::Flag,
+            OutOfBand::Pause => Self::Pause,
+        }
+    }
+}
+
+impl From<models::OutOfBand> for OutOfBand {
+    fn from(out_of_band: models::OutOfBand) -> Self {
+        match out_of_band {
+            models::OutOfBand::Flag => Self::Flag,
+            models::OutOfBand::Pause => Self::Pause,
+        }
+    }
+}
+
+#[derive(sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum BrewState {
+    Running,
+    Completed,
+    Failed,
+    Aborted,
+    Interrupted,
+}
+
+#[derive(sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum Phase {
+    Heating,
+    Holding,
+    Waiting,
 }
 
 #[derive(FromRow)]
 pub struct Brew {
+    pub id: i64,
     pub recipe_id: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub status: BrewState,
+    pub error: Option<String>,
+    pub progress_step: Option<i64>,
+    pub progress_phase: Option<Phase>,
+    pub progress_held: Option<i64>,
 }
 
 impl From<Recipe> for models::Recipe {
@@ -44,17 +113,335 @@ impl From<Recipe> for models::Recipe {
 
 impl From<Step> for models::Step {
     fn from(step: Step) -> Self {
+        let target_temperature = step.target_temperature.unwrap_or_default();
+        let duration = std::time::Duration::from_secs(step.duration.unwrap_or(0) as u64);
+        let message = step.message.unwrap_or_default();
+        let stirrer_on = step.stirrer;
+
+        let tolerance = models::Tolerance {
+            band: step.tolerance_band,
+            settle_samples: step.settle_samples.map(|samples| samples as u32),
+            out_of_band: step.out_of_band.map(Into::into),
+        };
+
+        match step.kind {
+            StepKind::Hold => Self::Hold(models::HoldStep {
+                target_temperature,
+                duration,
+                stirrer_on,
+                additions: vec![],
+                tolerance,
+            }),
+            StepKind::Ramp => Self::Ramp(models::RampStep {
+                target_temperature,
+                rate: step.rate.unwrap_or_default(),
+                stirrer_on,
+            }),
+            StepKind::Boil => Self::Boil(models::BoilStep {
+                duration,
+                stirrer_on,
+                additions: vec![],
+                tolerance,
+            }),
+            StepKind::Confirm => Self::Confirm(models::ConfirmStep {
+                message,
+                stirrer_on,
+            }),
+            StepKind::Reminder => Self::Reminder(models::ReminderStep {
+                message,
+                duration,
+                stirrer_on,
+            }),
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct Addition {
+    pub step: i64,
+    pub ingredient: String,
+    pub amount: String,
+    pub before_end: i64,
+}
+
+impl From<Addition> for models::Addition {
+    fn from(addition: Addition) -> Self {
         Self {
-            target_temperature: step.target_temperature,
-            duration: std::time::Duration::from_secs(step.duration as u64),
+            ingredient: addition.ingredient,
+            amount: addition.amount,
+            before_end: std::time::Duration::from_secs(addition.before_end as u64),
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct AdditionRecord {
+    pub step: i64,
+    pub position: i64,
+    pub alerted_at: Option<i64>,
+    pub acknowledged_at: Option<i64>,
+}
+
+impl From<AdditionRecord> for models::AdditionRecord {
+    fn from(addition: AdditionRecord) -> Self {
+        Self {
+            step: addition.step as usize,
+            position: addition.position as usize,
+            alerted_at: addition.alerted_at,
+            acknowledged_at: addition.acknowledged_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct Deviation {
+    pub step: i64,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub max_deviation: f32,
+}
+
+impl From<Deviation> for models::Deviation {
+    fn from(deviation: Deviation) -> Self {
+        Self {
+            step: deviation.step as usize,
+            started_at: deviation.started_at,
+            ended_at: deviation.ended_at,
+            max_deviation: deviation.max_deviation,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct Sample {
+    pub timestamp: i64,
+    pub temperature: f32,
+}
+
+impl From<Sample> for models::Sample {
+    fn from(sample: Sample) -> Self {
+        Self {
+            timestamp: sample.timestamp,
+            temperature: sample.temperature,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct StepRecord {
+    pub position: i64,
+    pub started_at: Option<i64>,
+    pub reached_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+impl From<StepRecord> for models::StepRecord {
+    fn from(step: StepRecord) -> Self {
+        Self {
+            position: step.position as usize,
+            started_at: step.started_at,
+            reached_at: step.reached_at,
+            finished_at: step.finished_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct Measurement {
+    pub timestamp: i64,
+    pub brew_temperature: Option<f32>,
+    pub heating: Option<bool>,
+    pub stirring: Option<bool>,
+}
+
+impl From<Measurement> for models::Measurement {
+    fn from(measurement: Measurement) -> Self {
+        Self {
+            timestamp: measurement.timestamp,
+            temperature: measurement.brew_temperature,
+            heater_on: measurement.heating,
+            stirrer_on: measurement.stirring,
+        }
+    }
+}
+
+impl From<BrewState> for models::BrewState {
+    fn from(state: BrewState) -> Self {
+        match state {
+            BrewState::Running => Self::Running,
+            BrewState::Completed => Self::Completed,
+            BrewState::Failed => Self::Failed,
+            BrewState::Aborted => Self::Aborted,
+            BrewState::Interrupted => Self::Interrupted,
+        }
+    }
+}
+
+impl From<models::BrewState> for BrewState {
+    fn from(state: models::BrewState) -> Self {
+        match state {
+            models::BrewState::Running => Self::Running,
+            models::BrewState::Completed => Self::Completed,
+            models::BrewState::Failed => Self::Failed,
+            models::BrewState::Aborted => Self::Aborted,
+            models::BrewState::Interrupted => Self::Interrupted,
+        }
+    }
+}
+
+impl From<Phase> for models::Phase {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Heating => Self::Heating,
+            Phase::Holding => Self::Holding,
+            Phase::Waiting => Self::Waiting,
+        }
+    }
+}
+
+impl From<models::Phase> for Phase {
+    fn from(phase: models::Phase) -> Self {
+        match phase {
+            models::Phase::Heating => Self::Heating,
+            models::Phase::Holding => Self::Holding,
+            models::Phase::Waiting => Self::Waiting,
+        }
+    }
+}
+
+impl From<Brew> for models::Brew {
+    fn from(brew: Brew) -> Self {
+        Self {
+            id: brew.id.into(),
+            recipe_id: brew.recipe_id.into(),
+            started_at: brew.started_at,
+            finished_at: brew.finished_at,
+            state: brew.status.into(),
+            error: brew.error,
+            progress: brew
+                .progress_step
+                .zip(brew.progress_phase)
+                .map(|(step, phase)| models::BrewProgress {
+                    step: step as usize,
+                    phase: phase.into(),
+                    held: std::time::Duration::from_secs(brew.progress_held.unwrap_or(0) as u64),
+                }),
+        }
+    }
+}
+
+/// Schema migrations in order, the schema version is the position in this list plus one. Never
+/// change a released migration, add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    include_str!("sql/migrations/0001_initial.sql"),
+    include_str!("sql/migrations/0002_brew_lifecycle.sql"),
+    include_str!("sql/migrations/0003_brew_log.sql"),
+    include_str!("sql/migrations/0004_step_stirrer.sql"),
+    include_str!("sql/migrations/0005_brew_progress.sql"),
+    include_str!("sql/migrations/0006_step_kinds.sql"),
+    include_str!("sql/migrations/0007_additions.sql"),
+    include_str!("sql/migrations/0008_tolerance.sql"),
+    include_str!("sql/migrations/0009_recipe_snapshot.sql"),
+];
+
+/// Bring the database schema up to date by applying all pending migrations, each one inside its
+/// own transaction.
+async fn migrate(pool: &SqlitePool, clock: &Clock) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL, applied_at INTEGER NOT NULL)",
+    )
+    .execute(pool)
+    .await?;
+
+    let (current,): (Option<i64>,) = sqlx::query_as("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+
+    let current = current.unwrap_or(0);
+
+    for (version, migration) in (1..).zip(MIGRATIONS).skip(current as usize) {
+        info!("Migrating database schema to version {version}");
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(migration).execute(&mut tx).await?;
+
+        sqlx::query("INSERT INTO schema_version (version, applied_at) VALUES (?, ?)")
+            .bind(version)
+            .bind(clock.timestamp())
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Insert `steps` of recipe `id` and their additions one after the other as part of transaction
+/// `tx`.
+async fn insert_steps(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: i64,
+    steps: Vec<models::Step>,
+) -> Result<()> {
+    for (pos, step) in steps.into_iter().enumerate() {
+        for (position, addition) in step.additions().iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO additions (recipe_id, step, position, ingredient, amount, before_end) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(id)
+            .bind(pos as i64)
+            .bind(position as i64)
+            .bind(&addition.ingredient)
+            .bind(&addition.amount)
+            .bind(addition.before_end.as_secs() as i64)
+            .execute(&mut *tx)
+            .await?;
         }
+
+        let stirrer_on = step.stirrer_on();
+        let target_temperature = step.target_temperature();
+        let tolerance = step.tolerance().cloned().unwrap_or_default();
+
+        let (kind, duration, rate, message) = match step {
+            models::Step::Hold(step) => (StepKind::Hold, Some(step.duration), None, None),
+            models::Step::Ramp(step) => (StepKind::Ramp, None, Some(step.rate), None),
+            models::Step::Boil(step) => (StepKind::Boil, Some(step.duration), None, None),
+            models::Step::Confirm(step) => (StepKind::Confirm, None, None, Some(step.message)),
+            models::Step::Reminder(step) => (
+                StepKind::Reminder,
+                Some(step.duration),
+                None,
+                Some(step.message),
+            ),
+        };
+
+        sqlx::query(
+            "INSERT INTO steps (recipe_id, position, kind, target_temperature, duration, rate, message, stirrer, tolerance_band, settle_samples, out_of_band) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(pos as i64)
+        .bind(kind)
+        .bind(target_temperature)
+        .bind(duration.map(|duration| duration.as_secs() as i64))
+        .bind(rate)
+        .bind(message)
+        .bind(stirrer_on)
+        .bind(tolerance.band)
+        .bind(tolerance.settle_samples.map(i64::from))
+        .bind(tolerance.out_of_band.map(OutOfBand::from))
+        .execute(&mut *tx)
+        .await?;
     }
+
+    Ok(())
 }
 
 impl Database {
     /// Create new database. Use the environment variable `DATABASE_URL` to point to a valid sqlite
-    /// database file.
-    pub async fn new(database: Option<String>) -> Result<Self> {
+    /// database file. Timestamps are taken from `clock`.
+    pub async fn new(database: Option<String>, clock: Clock) -> Result<Self> {
         let url = database.unwrap_or_else(|| "sqlite::memory:".to_string());
 
         info!("Connecting to {url}");
@@ -66,11 +453,9 @@ impl Database {
 
         let pool = SqlitePoolOptions::new().connect_with(options).await?;
 
-        sqlx::query(include_str!("sql/create.sql"))
-            .execute(&pool)
-            .await?;
+        migrate(&pool, &clock).await?;
 
-        Ok(Self { pool })
+        Ok(Self { pool, clock })
     }
 
     /// Get all known recipes.
@@ -96,13 +481,29 @@ impl Database {
             .fetch_one(&self.pool)
             .await?;
 
-        let steps = sqlx::query_as::<_, Step>("SELECT * from steps WHERE recipe_id = ?")
-            .bind(id)
-            .fetch_all(&self.pool)
-            .await?
-            .into_iter()
-            .map(|step| step.into())
-            .collect::<Vec<models::Step>>();
+        let mut steps =
+            sqlx::query_as::<_, Step>("SELECT * from steps WHERE recipe_id = ? ORDER BY position")
+                .bind(id)
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|step| step.into())
+                .collect::<Vec<models::Step>>();
+
+        let additions = sqlx::query_as::<_, Addition>(
+            "SELECT step, ingredient, amount, before_end FROM additions WHERE recipe_id = ? ORDER BY step, position",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for addition in additions {
+            match steps.get_mut(addition.step as usize) {
+                Some(models::Step::Hold(step)) => step.additions.push(addition.into()),
+                Some(models::Step::Boil(step)) => step.additions.push(addition.into()),
+                _ => {}
+            }
+        }
 
         let recipe = models::Recipe {
             id: recipe.id.into(),
@@ -114,70 +515,664 @@ impl Database {
         Ok(recipe)
     }
 
-    /// Add a recipe.
+    /// Add a recipe and its steps in a single transaction.
     #[instrument]
     pub async fn add_recipe(&self, recipe: models::NewRecipe) -> Result<models::NewRecipeResponse> {
+        let mut tx = self.pool.begin().await?;
+
         let result = sqlx::query("INSERT INTO recipes (title, description) VALUES (?, ?)")
             .bind(recipe.name)
             .bind(recipe.description)
-            .execute(&self.pool)
+            .execute(&mut tx)
             .await?;
 
         let id = result.last_insert_rowid();
 
-        let futures = recipe
-            .steps
-            .into_iter()
-            .enumerate()
-            .map(|(pos, step)| async move {
-                sqlx::query(
-                    "INSERT INTO steps (recipe_id, position, target_temperature, duration) VALUES (?, ?, ?, ?)",
-                )
+        insert_steps(&mut tx, id, recipe.steps).await?;
+
+        tx.commit().await?;
+
+        Ok(models::NewRecipeResponse { id: id.into() })
+    }
+
+    /// Replace name, description and steps of recipe `id` atomically. Brews store the recipe they
+    /// brewed, only brews recorded before that are read from the recipe and refuse the update.
+    #[instrument]
+    pub async fn update_recipe(
+        &self,
+        id: models::RecipeId,
+        recipe: models::NewRecipe,
+    ) -> Result<()> {
+        let recipe_id = id;
+        let id: i64 = id.into();
+        let mut tx = self.pool.begin().await?;
+
+        let (brews,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM brews WHERE recipe_id = ? AND recipe IS NULL")
                 .bind(id)
-                .bind(pos as i64)
-                .bind(step.target_temperature)
-                .bind(step.duration.as_secs() as i64)
-                .execute(&self.pool)
+                .fetch_one(&mut tx)
                 .await?;
 
-                Ok::<_, AppError>(())
-            })
-            .collect::<Vec<_>>();
+        if brews > 0 {
+            return Err(AppError::RecipeInUse(recipe_id));
+        }
+
+        let result = sqlx::query("UPDATE recipes SET title = ?, description = ? WHERE id = ?")
+            .bind(recipe.name)
+            .bind(recipe.description)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
 
-        try_join_all(futures).await?;
+        sqlx::query("DELETE FROM additions WHERE recipe_id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
 
-        Ok(models::NewRecipeResponse { id: id.into() })
+        sqlx::query("DELETE FROM steps WHERE recipe_id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        insert_steps(&mut tx, id, recipe.steps).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Delete recipe `id` and its steps. Fails if any brew references the recipe.
+    #[instrument]
+    pub async fn delete_recipe(&self, id: models::RecipeId) -> Result<()> {
+        let recipe_id = id;
+        let id: i64 = id.into();
+        let mut tx = self.pool.begin().await?;
+
+        let (brews,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM brews WHERE recipe_id = ?")
+            .bind(id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        if brews > 0 {
+            return Err(AppError::RecipeInUse(recipe_id));
+        }
+
+        sqlx::query("DELETE FROM additions WHERE recipe_id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("DELETE FROM steps WHERE recipe_id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM recipes WHERE id = ?")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Add a running brew of `recipe` started now. The recipe is stored with the brew, so that
+    /// the brew log stays a historical record.
+    #[instrument]
+    pub async fn add_brew(&self, recipe: &models::Recipe) -> Result<models::NewBrewResponse> {
+        let recipe_id: i64 = recipe.id.into();
+        let snapshot = serde_json::to_string(recipe)?;
+
+        let id = sqlx::query(
+            "INSERT INTO brews (recipe_id, recipe, started_at, status) VALUES (?, ?, ?, ?)",
+        )
+        .bind(recipe_id)
+        .bind(snapshot)
+        .bind(self.clock.timestamp())
+        .bind(BrewState::Running)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(models::NewBrewResponse { id: id.into() })
+    }
+
+    /// Mark brew as finished now with final `state` and optional `error` message.
+    #[instrument]
+    pub async fn finish_brew(
+        &self,
+        id: models::BrewId,
+        state: models::BrewState,
+        error: Option<String>,
+    ) -> Result<()> {
+        let id: i64 = id.into();
+
+        sqlx::query("UPDATE brews SET finished_at = ?, status = ?, error = ? WHERE id = ?")
+            .bind(self.clock.timestamp())
+            .bind(BrewState::from(state))
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
     }
 
-    /// Add a brew.
+    /// Mark all brews still recorded as running as interrupted and return how many there were.
     #[instrument]
-    pub async fn add_brew(&self, id: models::RecipeId) -> Result<models::NewBrewResponse> {
+    pub async fn interrupt_running_brews(&self) -> Result<u64> {
+        let result = sqlx::query("UPDATE brews SET status = ? WHERE status = ?")
+            .bind(BrewState::Interrupted)
+            .bind(BrewState::Running)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Mark the interrupted brew `id` as running again.
+    #[instrument]
+    pub async fn continue_brew(&self, id: models::BrewId) -> Result<()> {
         let id: i64 = id.into();
 
-        let id = sqlx::query("INSERT INTO brews (recipe_id) VALUES (?)")
+        sqlx::query("UPDATE brews SET status = ? WHERE id = ?")
+            .bind(BrewState::Running)
             .bind(id)
             .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Persist the `progress` of brew `id`.
+    #[instrument]
+    pub async fn save_progress(
+        &self,
+        id: models::BrewId,
+        progress: models::BrewProgress,
+    ) -> Result<()> {
+        let id: i64 = id.into();
+
+        sqlx::query(
+            "UPDATE brews SET progress_step = ?, progress_phase = ?, progress_held = ? WHERE id = ?",
+        )
+        .bind(progress.step as i64)
+        .bind(Phase::from(progress.phase))
+        .bind(progress.held.as_secs() as i64)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all brews, most recent first.
+    #[instrument]
+    pub async fn brews(&self) -> Result<models::Brews> {
+        let brews = sqlx::query_as::<_, Brew>("SELECT * FROM brews ORDER BY id DESC")
+            .fetch_all(&self.pool)
             .await?
-            .last_insert_rowid();
+            .into_iter()
+            .map(|row| row.into())
+            .collect::<Vec<models::Brew>>();
 
-        Ok(models::NewBrewResponse { id: id.into() })
+        Ok(models::Brews { brews })
     }
 
-    /// Add new sample.
+    /// Get all brews in `state`, most recent first.
     #[instrument]
-    pub async fn add_sample(&self, id: models::BrewId, temperature: f32) -> Result<()> {
+    pub async fn brews_in_state(&self, state: models::BrewState) -> Result<models::Brews> {
+        let brews =
+            sqlx::query_as::<_, Brew>("SELECT * FROM brews WHERE status = ? ORDER BY id DESC")
+                .bind(BrewState::from(state))
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|row| row.into())
+                .collect::<Vec<models::Brew>>();
+
+        Ok(models::Brews { brews })
+    }
+
+    /// Get brew by id.
+    #[instrument]
+    pub async fn brew(&self, id: models::BrewId) -> Result<models::Brew> {
+        let id: i64 = id.into();
+
+        let brew = sqlx::query_as::<_, Brew>("SELECT * FROM brews WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(brew.into())
+    }
+
+    /// Add new sample from the current `device` state.
+    #[instrument]
+    pub async fn add_sample(&self, id: models::BrewId, device: &models::Device) -> Result<()> {
         let id: i64 = id.into();
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?;
 
         sqlx::query(
-            "INSERT INTO brew_measurements (brew_id, timestamp, brew_temperature) VALUES (?, ?, ?)",
+            "INSERT INTO brew_measurements (brew_id, timestamp, brew_temperature, heating, stirring) VALUES (?, ?, ?, ?, ?)",
         )
         .bind(id)
-        .bind(timestamp.as_secs() as i64)
-        .bind(temperature)
+        .bind(self.clock.timestamp())
+        .bind(device.current_temperature)
+        .bind(device.heater_on)
+        .bind(device.stirrer_on)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
+
+    /// Get all measurements of brew `id` ordered by time.
+    #[instrument]
+    pub async fn measurements(&self, id: models::BrewId) -> Result<Vec<models::Measurement>> {
+        let id: i64 = id.into();
+
+        let measurements = sqlx::query_as::<_, Measurement>(
+            "SELECT timestamp, brew_temperature, heating, stirring FROM brew_measurements WHERE brew_id = ? ORDER BY timestamp",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+
+        Ok(measurements)
+    }
+
+    /// Record that step `position` of brew `id` started heating now, replacing an earlier record
+    /// of a restarted step and its additions.
+    #[instrument]
+    pub async fn start_brew_step(&self, id: models::BrewId, position: usize) -> Result<()> {
+        let id: i64 = id.into();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM brew_steps WHERE brew_id = ? AND position = ?")
+            .bind(id)
+            .bind(position as i64)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("DELETE FROM brew_additions WHERE brew_id = ? AND step = ?")
+            .bind(id)
+            .bind(position as i64)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("INSERT INTO brew_steps (brew_id, position, started_at) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(position as i64)
+            .bind(self.clock.timestamp())
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Record that step `position` of brew `id` reached its target temperature now, unless it was
+    /// reached before an interruption.
+    #[instrument]
+    pub async fn reach_brew_step(&self, id: models::BrewId, position: usize) -> Result<()> {
+        let id: i64 = id.into();
+
+        sqlx::query("UPDATE brew_steps SET reached_at = COALESCE(reached_at, ?) WHERE brew_id = ? AND position = ?")
+            .bind(self.clock.timestamp())
+            .bind(id)
+            .bind(position as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Record that step `position` of brew `id` finished now.
+    #[instrument]
+    pub async fn finish_brew_step(&self, id: models::BrewId, position: usize) -> Result<()> {
+        let id: i64 = id.into();
+
+        sqlx::query("UPDATE brew_steps SET finished_at = ? WHERE brew_id = ? AND position = ?")
+            .bind(self.clock.timestamp())
+            .bind(id)
+            .bind(position as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the recorded step boundaries of brew `id`.
+    #[instrument]
+    pub async fn brew_steps(&self, id: models::BrewId) -> Result<Vec<models::StepRecord>> {
+        let id: i64 = id.into();
+
+        let steps = sqlx::query_as::<_, StepRecord>(
+            "SELECT position, started_at, reached_at, finished_at FROM brew_steps WHERE brew_id = ? ORDER BY position",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+
+        Ok(steps)
+    }
+
+    /// Record that addition `position` of step `step` of brew `id` was announced now.
+    #[instrument]
+    pub async fn alert_addition(
+        &self,
+        id: models::BrewId,
+        step: usize,
+        position: usize,
+    ) -> Result<()> {
+        let id: i64 = id.into();
+
+        sqlx::query(
+            "INSERT INTO brew_additions (brew_id, step, position, alerted_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(step as i64)
+        .bind(position as i64)
+        .bind(self.clock.timestamp())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the positions of the additions of step `step` of brew `id` that were announced.
+    #[instrument]
+    pub async fn alerted_additions(&self, id: models::BrewId, step: usize) -> Result<Vec<usize>> {
+        let id: i64 = id.into();
+
+        let positions: Vec<(i64,)> = sqlx::query_as(
+            "SELECT position FROM brew_additions WHERE brew_id = ? AND step = ? AND alerted_at IS NOT NULL",
+        )
+        .bind(id)
+        .bind(step as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(positions
+            .into_iter()
+            .map(|(position,)| position as usize)
+            .collect())
+    }
+
+    /// Record that addition `position` of step `step` of brew `id` was acknowledged now. Fails
+    /// with `RowNotFound` if the addition was not announced yet.
+    #[instrument]
+    pub async fn acknowledge_addition(
+        &self,
+        id: models::BrewId,
+        step: usize,
+        position: usize,
+    ) -> Result<()> {
+        let id: i64 = id.into();
+
+        let result = sqlx::query(
+            "UPDATE brew_additions SET acknowledged_at = COALESCE(acknowledged_at, ?) WHERE brew_id = ? AND step = ? AND position = ?",
+        )
+        .bind(self.clock.timestamp())
+        .bind(id)
+        .bind(step as i64)
+        .bind(position as i64)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        Ok(())
+    }
+
+    /// Get the recorded additions of brew `id`.
+    #[instrument]
+    pub async fn brew_additions(&self, id: models::BrewId) -> Result<Vec<models::AdditionRecord>> {
+        let id: i64 = id.into();
+
+        let additions = sqlx::query_as::<_, AdditionRecord>(
+            "SELECT step, position, alerted_at, acknowledged_at FROM brew_additions WHERE brew_id = ? ORDER BY step, position",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+
+        Ok(additions)
+    }
+
+    /// Record that the temperature of step `step` of brew `id` left the tolerance band now by
+    /// `deviation` Kelvin and return the record identifier.
+    #[instrument]
+    pub async fn start_deviation(
+        &self,
+        id: models::BrewId,
+        step: usize,
+        deviation: f32,
+    ) -> Result<i64> {
+        let id: i64 = id.into();
+
+        let result = sqlx::query(
+            "INSERT INTO brew_deviations (brew_id, step, started_at, max_deviation) VALUES (?, ?, ?, ?)",
+        )
+        .bind(id)
+        .bind(step as i64)
+        .bind(self.clock.timestamp())
+        .bind(deviation)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Raise the largest deviation of deviation record `record` to `deviation` if it is larger.
+    #[instrument]
+    pub async fn update_deviation(&self, record: i64, deviation: f32) -> Result<()> {
+        sqlx::query(
+            "UPDATE brew_deviations SET max_deviation = MAX(max_deviation, ?) WHERE id = ?",
+        )
+        .bind(deviation)
+        .bind(record)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that the temperature of deviation record `record` returned into the band now.
+    #[instrument]
+    pub async fn end_deviation(&self, record: i64) -> Result<()> {
+        sqlx::query("UPDATE brew_deviations SET ended_at = ? WHERE id = ?")
+            .bind(self.clock.timestamp())
+            .bind(record)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the recorded deviations of brew `id`.
+    #[instrument]
+    pub async fn brew_deviations(&self, id: models::BrewId) -> Result<Vec<models::Deviation>> {
+        let id: i64 = id.into();
+
+        let deviations = sqlx::query_as::<_, Deviation>(
+            "SELECT step, started_at, ended_at, max_deviation FROM brew_deviations WHERE brew_id = ? ORDER BY started_at",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
+
+        Ok(deviations)
+    }
+
+    /// Get the recipe brew `id` was started with. Brews started before recipes were stored with
+    /// them fall back to the current recipe.
+    #[instrument]
+    pub async fn brew_recipe(&self, id: models::BrewId) -> Result<models::Recipe> {
+        let brew: i64 = id.into();
+
+        let (recipe_id, snapshot): (i64, Option<String>) =
+            sqlx::query_as("SELECT recipe_id, recipe FROM brews WHERE id = ?")
+                .bind(brew)
+                .fetch_one(&self.pool)
+                .await?;
+
+        match snapshot {
+            Some(snapshot) => Ok(serde_json::from_str(&snapshot)?),
+            None => self.recipe(recipe_id.into()).await,
+        }
+    }
+
+    /// Get the complete log of brew `id`.
+    #[instrument]
+    pub async fn brew_log(&self, id: models::BrewId) -> Result<models::BrewLog> {
+        let brew = self.brew(id).await?;
+        let recipe = self.brew_recipe(id).await?;
+        let steps = self.brew_steps(id).await?;
+        let additions = self.brew_additions(id).await?;
+        let deviations = self.brew_deviations(id).await?;
+        let measurements = self.measurements(id).await?;
+
+        Ok(models::BrewLog {
+            brew,
+            recipe,
+            steps,
+            additions,
+            deviations,
+            measurements,
+        })
+    }
+
+    /// Get samples of brew `id` between `since` and `until` (inclusive, in seconds since the Unix
+    /// epoch). Samples are averaged over buckets of `bucket` seconds.
+    #[instrument]
+    pub async fn samples(
+        &self,
+        id: models::BrewId,
+        since: Option<i64>,
+        until: Option<i64>,
+        bucket: NonZeroU32,
+    ) -> Result<models::Samples> {
+        let id: i64 = id.into();
+        let bucket = bucket.get();
+
+        let samples = sqlx::query_as::<_, Sample>(
+            "SELECT (timestamp / ?) * ? AS timestamp, AVG(brew_temperature) AS temperature
+            FROM brew_measurements
+            WHERE brew_id = ? AND timestamp >= ? AND timestamp <= ? AND brew_temperature IS NOT NULL
+            GROUP BY 1 ORDER BY 1",
+        )
+        .bind(bucket)
+        .bind(bucket)
+        .bind(id)
+        .bind(since.unwrap_or(i64::MIN))
+        .bind(until.unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| row.into())
+        .collect::<Vec<models::Sample>>();
+
+        Ok(models::Samples { samples })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn hold(target_temperature: f32) -> models::Step {
+        models::Step::Hold(models::HoldStep {
+            target_temperature,
+            duration: Duration::from_secs(60),
+            stirrer_on: false,
+            additions: vec![],
+            tolerance: models::Tolerance::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn failing_step_leaves_no_recipe() -> Result<()> {
+        let db = Database::new(None, Clock::starting_at(Duration::ZERO, 1.0)).await?;
+
+        // Reject the second step after the recipe and the first step were inserted.
+        sqlx::query(
+            "CREATE TRIGGER reject_step BEFORE INSERT ON steps WHEN NEW.position = 1 BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(&db.pool)
+        .await?;
+
+        let recipe = models::NewRecipe {
+            name: "Pils".to_string(),
+            description: String::new(),
+            steps: vec![hold(63.0), hold(72.0)],
+        };
+
+        assert!(db.add_recipe(recipe).await.is_err());
+        assert!(db.recipes().await?.recipes.is_empty());
+
+        let (steps,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM steps")
+            .fetch_one(&db.pool)
+            .await?;
+
+        assert_eq!(steps, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_keeps_brewed_recipe() -> Result<()> {
+        let db = Database::new(None, Clock::starting_at(Duration::ZERO, 1.0)).await?;
+
+        let recipe = models::NewRecipe {
+            name: "Pils".to_string(),
+            description: String::new(),
+            steps: vec![hold(63.0)],
+        };
+
+        let id = db.add_recipe(recipe).await?.id;
+        let brew = db.add_brew(&db.recipe(id).await?).await?.id;
+
+        let recipe = models::NewRecipe {
+            name: "Helles".to_string(),
+            description: String::new(),
+            steps: vec![hold(65.0)],
+        };
+
+        db.update_recipe(id, recipe).await?;
+
+        assert_eq!(db.recipe(id).await?.name, "Helles");
+        assert_eq!(db.brew_recipe(brew).await?.name, "Pils");
+        assert!(matches!(
+            db.delete_recipe(id).await,
+            Err(AppError::RecipeInUse(_))
+        ));
+
+        Ok(())
+    }
 }
@@ -1,26 +1,45 @@
 use crate::devices::Device;
-use crate::Result;
-use std::path::Path;
-use tracing::{instrument, trace};
+use crate::{AppError, Result};
+use std::path::{Path, PathBuf};
+use tokio::time::Duration;
+use tracing::{instrument, trace, warn};
 
 #[derive(Debug)]
 pub struct Brewslave {
-    client: comm::Comm,
+    path: PathBuf,
+    protocol: comm::Protocol,
+    client: Option<comm::Comm>,
 }
 
 impl Brewslave {
-    pub fn new(path: &Path) -> Result<Self> {
+    /// Open the Brewslave at `path` speaking `protocol`. If the port cannot be opened yet, the
+    /// device starts out disconnected and is reconnected by the device task.
+    pub async fn new(path: &Path, protocol: comm::Protocol) -> Result<Self> {
+        let client = match comm::Comm::with_protocol(path, protocol).await {
+            Ok(client) => Some(client),
+            Err(err) => {
+                warn!("Could not open {}: {}", path.display(), err);
+                None
+            }
+        };
+
         Ok(Self {
-            client: comm::Comm::new(path)?,
+            path: path.to_path_buf(),
+            protocol,
+            client,
         })
     }
+
+    fn client(&self) -> Result<&comm::Comm> {
+        self.client.as_ref().ok_or(AppError::DeviceDisconnected)
+    }
 }
 
 impl Device for Brewslave {
     /// Set up the serial connection and poll for new temperature, stirrer and heater values.
     #[instrument(skip_all)]
     async fn read(&self) -> Result<models::Device> {
-        let state = self.client.read_state().await?;
+        let state = self.client()?.read_state().await?;
         trace!("read {:?}", state);
 
         Ok(models::Device {
@@ -32,8 +51,54 @@ impl Device for Brewslave {
         })
     }
 
+    fn info(&self) -> models::DeviceInfo {
+        let info = self
+            .client
+            .as_ref()
+            .map_or_else(comm::Info::legacy, |client| client.info().clone());
+
+        models::DeviceInfo {
+            firmware_version: info
+                .firmware_version
+                .map(|(major, minor, patch)| format!("{major}.{minor}.{patch}")),
+            protocol_version: info.protocol_version,
+            stirrer: info.stirrer,
+            heater: info.heater,
+            watchdog: info.watchdog,
+            heater_duty: info.heater_duty,
+            sensors: info.sensors,
+        }
+    }
+
     #[instrument]
     async fn set_temperature(&mut self, temperature: f32) -> Result<()> {
-        Ok(self.client.set_temperature(temperature).await?)
+        Ok(self.client()?.set_temperature(temperature).await?)
+    }
+
+    #[instrument]
+    async fn set_heater_duty(&mut self, duty: f32) -> Result<()> {
+        Ok(self.client()?.set_heater_duty(duty).await?)
+    }
+
+    #[instrument]
+    async fn set_stirrer(&mut self, on: bool) -> Result<()> {
+        Ok(self.client()?.write_stirrer(on).await?)
+    }
+
+    #[instrument]
+    async fn heartbeat(&mut self, timeout: Duration) -> Result<()> {
+        Ok(self.client()?.heartbeat(timeout).await?)
+    }
+
+    fn connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Drop the current serial stream and open the port again.
+    #[instrument]
+    async fn reconnect(&mut self) -> Result<()> {
+        self.client = None;
+        self.client = Some(comm::Comm::with_protocol(&self.path, self.protocol).await?);
+        Ok(())
     }
 }
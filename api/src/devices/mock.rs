@@ -1,49 +1,187 @@
+use crate::clock::Clock;
+use crate::config::Simulation;
 use crate::devices::Device;
 use crate::Result;
-use std::time::Instant;
+use rand::Rng;
+use std::sync::Mutex;
+use std::time::Duration;
 use tracing::instrument;
 
+/// Specific heat capacity of water in J/(kg K), assuming one kilogram per litre.
+const SPECIFIC_HEAT: f32 = 4186.0;
+
+/// Boiling point of water at sea level, the heat is spent on evaporation above it.
+const BOILING_POINT: f32 = 100.0;
+
+/// Longest simulated time step, so that the thermostat is evaluated often enough.
+const MAX_STEP: Duration = Duration::from_secs(1);
+
+/// Simulated kettle state.
+#[derive(Debug)]
+struct Kettle {
+    temperature: f32,
+    /// Temperature seen by the sensor lagging behind the kettle temperature.
+    sensor: f32,
+    /// Target temperature or `None` after the watchdog turned the heater off.
+    target_temperature: Option<f32>,
+    heater_on: bool,
+    /// Heater duty set by the server overriding the thermostat.
+    duty: Option<f32>,
+    /// Clock time of the last simulation step.
+    last_update: Duration,
+    /// Clock time of the last heartbeat and the requested timeout.
+    watchdog: Option<(Duration, Duration)>,
+}
+
+/// Mock device simulating a kettle heated by a thermostat controlled heater with a watchdog that
+/// turns the heater off when heartbeats stop. The heater can also be driven with a duty, which is
+/// simulated by its mean power.
 #[derive(Debug)]
 pub struct Mock {
-    target_temperature: f32,
-    last_time: Instant,
-    last_temperature: f32,
+    simulation: Simulation,
+    stirrer_on: bool,
+    kettle: Mutex<Kettle>,
+    clock: Clock,
 }
 
 impl Mock {
-    pub fn new() -> Self {
+    /// Create a mock simulating time on `clock`, which also applies the simulation speedup.
+    pub fn new(simulation: Simulation, clock: Clock) -> Self {
+        let kettle = Kettle {
+            temperature: simulation.ambient_temperature,
+            sensor: simulation.ambient_temperature,
+            target_temperature: Some(simulation.ambient_temperature),
+            heater_on: false,
+            duty: None,
+            last_update: clock.elapsed(),
+            watchdog: None,
+        };
+
         Self {
-            target_temperature: 20.0,
-            last_time: Instant::now(),
-            last_temperature: 19.0,
+            simulation,
+            stirrer_on: false,
+            kettle: Mutex::new(kettle),
+            clock,
+        }
+    }
+
+    /// Advance the simulation up to now and return the sensor temperature, target temperature and
+    /// heater state.
+    fn update(&self) -> (f32, Option<f32>, bool) {
+        let mut kettle = self.kettle.lock().unwrap();
+        let now = self.clock.elapsed();
+        let capacity = self.simulation.volume * SPECIFIC_HEAT;
+
+        while kettle.last_update < now {
+            let step = (now - kettle.last_update).min(MAX_STEP);
+            kettle.last_update += step;
+
+            if let Some((last, timeout)) = kettle.watchdog {
+                if kettle.last_update - last > timeout {
+                    kettle.watchdog = None;
+                    kettle.target_temperature = None;
+                    kettle.duty = None;
+                }
+            }
+
+            match (kettle.duty, kettle.target_temperature) {
+                (Some(duty), _) => kettle.heater_on = duty > 0.0,
+                (None, Some(target)) if kettle.sensor >= target => kettle.heater_on = false,
+                (None, Some(target)) if kettle.sensor < target - self.simulation.hysteresis => {
+                    kettle.heater_on = true
+                }
+                (None, Some(_)) => {}
+                (None, None) => kettle.heater_on = false,
+            }
+
+            let heating = match kettle.duty {
+                Some(duty) => duty * self.simulation.heater_power,
+                None if kettle.heater_on => self.simulation.heater_power,
+                None => 0.0,
+            };
+
+            let loss = self.simulation.heat_loss
+                * (kettle.temperature - self.simulation.ambient_temperature);
+
+            kettle.temperature = (kettle.temperature
+                + (heating - loss) / capacity * step.as_secs_f32())
+            .min(BOILING_POINT);
+
+            kettle.sensor = if self.simulation.sensor_lag > 0.0 {
+                let weight = 1.0 - (-step.as_secs_f32() / self.simulation.sensor_lag).exp();
+                kettle.sensor + (kettle.temperature - kettle.sensor) * weight
+            } else {
+                kettle.temperature
+            };
         }
+
+        (kettle.sensor, kettle.target_temperature, kettle.heater_on)
     }
 
-    fn current_temperature(&self) -> f32 {
-        let duration = Instant::now() - self.last_time;
-        let delta_temp = self.target_temperature - self.last_temperature;
-        let y = (duration.as_secs_f32() / 5.0).tanh();
-        self.last_temperature + y * delta_temp
+    /// Simulated sensor reading of `temperature`.
+    fn measure(&self, temperature: f32) -> f32 {
+        let noise = self.simulation.sensor_noise;
+
+        if noise > 0.0 {
+            temperature + rand::thread_rng().gen_range(-noise..=noise)
+        } else {
+            temperature
+        }
     }
 }
 
 impl Device for Mock {
     #[instrument]
     async fn read(&self) -> Result<models::Device> {
+        let (temperature, target_temperature, heater_on) = self.update();
+
         Ok(models::Device {
-            current_temperature: Some(self.current_temperature()),
-            target_temperature: Some(self.target_temperature),
-            stirrer_on: false,
-            heater_on: false,
+            current_temperature: Some(self.measure(temperature)),
+            target_temperature,
+            stirrer_on: self.stirrer_on,
+            heater_on,
             serial_problem: false,
         })
     }
 
+    fn info(&self) -> models::DeviceInfo {
+        models::DeviceInfo {
+            firmware_version: None,
+            protocol_version: 0,
+            stirrer: true,
+            heater: true,
+            watchdog: true,
+            heater_duty: true,
+            sensors: 1,
+        }
+    }
+
     #[instrument]
     async fn set_temperature(&mut self, temperature: f32) -> Result<()> {
-        self.last_time = Instant::now();
-        self.last_temperature = self.current_temperature();
-        self.target_temperature = temperature;
+        self.update();
+        let mut kettle = self.kettle.lock().unwrap();
+        kettle.target_temperature = Some(temperature);
+        kettle.duty = None;
+        Ok(())
+    }
+
+    #[instrument]
+    async fn set_heater_duty(&mut self, duty: f32) -> Result<()> {
+        self.update();
+        self.kettle.lock().unwrap().duty = Some(duty.clamp(0.0, 1.0));
+        Ok(())
+    }
+
+    #[instrument]
+    async fn heartbeat(&mut self, timeout: Duration) -> Result<()> {
+        self.update();
+        self.kettle.lock().unwrap().watchdog = Some((self.clock.elapsed(), timeout));
+        Ok(())
+    }
+
+    #[instrument]
+    async fn set_stirrer(&mut self, on: bool) -> Result<()> {
+        self.stirrer_on = on;
         Ok(())
     }
 }
@@ -1,9 +1,22 @@
-use crate::Result;
+use crate::clock::Clock;
+use crate::{config, AppError, Result};
+use tokio::select;
 use tokio::sync::{mpsc, oneshot};
-use tracing::instrument;
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::{info, instrument, trace, warn};
 
 pub mod brewslave;
 pub mod mock;
+pub mod pid;
+
+/// Number of consecutive failures after which the connection is considered broken.
+const MAX_CONSECUTIVE_ERRORS: u32 = 3;
+
+/// First delay between reconnection attempts, doubled after each failed attempt.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound for the delay between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 /// An external device capable of reading current real and set temperature as well as allowing
 /// setting a target temperature.
@@ -11,8 +24,35 @@ pub trait Device {
     /// Read model state from the device.
     async fn read(&self) -> Result<models::Device>;
 
+    /// Firmware identification and capabilities.
+    fn info(&self) -> models::DeviceInfo;
+
     /// Set target temperature.
     async fn set_temperature(&mut self, temperature: f32) -> Result<()>;
+
+    /// Turn the stirrer on or off.
+    async fn set_stirrer(&mut self, on: bool) -> Result<()>;
+
+    /// Drive the heater with `duty` between zero and one instead of the device thermostat until
+    /// the next target temperature is set.
+    async fn set_heater_duty(&mut self, _duty: f32) -> Result<()> {
+        Err(AppError::Unsupported("heater duty"))
+    }
+
+    /// Keep the device watchdog alive, so that the heater stays on for another `timeout`.
+    async fn heartbeat(&mut self, _timeout: Duration) -> Result<()> {
+        Err(AppError::Unsupported("watchdog"))
+    }
+
+    /// Whether the device is currently connected.
+    fn connected(&self) -> bool {
+        true
+    }
+
+    /// Re-establish the connection to the device after repeated failures.
+    async fn reconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Used by the caller to get a result back from a command.
@@ -27,27 +67,464 @@ pub enum Command {
         temperature: f32,
         resp: Responder<()>,
     },
+    SetStirrer {
+        on: bool,
+        resp: Responder<()>,
+    },
+    Health {
+        resp: Responder<models::DeviceHealth>,
+    },
+    Info {
+        resp: Responder<models::DeviceInfo>,
+    },
 }
 
 /// Type alias for the command sender.
 pub type Sender = mpsc::Sender<Command>;
 
+/// Connection supervision state.
+struct Health {
+    connected: bool,
+    consecutive_errors: u32,
+    reconnects: u64,
+    last_error: Option<String>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl Health {
+    /// Start supervising a device that is `connected` or needs to be reconnected right away.
+    fn new(connected: bool) -> Self {
+        Self {
+            connected,
+            consecutive_errors: 0,
+            reconnects: 0,
+            last_error: None,
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// Track the outcome of a device operation and mark the connection as broken if it failed too
+    /// often in a row.
+    fn record<T>(&mut self, result: &Result<T>) {
+        match result {
+            Ok(_) => {
+                self.consecutive_errors = 0;
+            }
+            Err(err) => {
+                self.consecutive_errors += 1;
+                self.last_error = Some(err.to_string());
+
+                if self.connected && self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                    warn!(
+                        "Device failed {} times in a row, reconnecting",
+                        self.consecutive_errors
+                    );
+                    self.connected = false;
+                    self.backoff = MIN_BACKOFF;
+                    self.next_attempt = Instant::now();
+                }
+            }
+        }
+    }
+
+    fn problem(&self) -> bool {
+        !self.connected || self.consecutive_errors > 0
+    }
+
+    fn to_model(
+        &self,
+        watchdog: models::Watchdog,
+        heater_control: models::HeaterControl,
+    ) -> models::DeviceHealth {
+        models::DeviceHealth {
+            connected: self.connected,
+            consecutive_errors: self.consecutive_errors,
+            reconnects: self.reconnects,
+            last_error: self.last_error.clone(),
+            watchdog,
+            heater_control,
+        }
+    }
+}
+
+/// Heartbeat state, all times are clock times.
+struct Watchdog {
+    /// Configured timeout or `None` if disabled.
+    timeout: Option<Duration>,
+    next: Duration,
+    last_ack: Option<Duration>,
+}
+
+impl Watchdog {
+    fn new(config: config::Watchdog) -> Self {
+        Self {
+            timeout: (config.timeout > 0).then(|| Duration::from_secs(config.timeout)),
+            next: Duration::ZERO,
+            last_ack: None,
+        }
+    }
+
+    /// Whether heartbeats are sent to `device`.
+    fn active<D: Device>(&self, device: &D) -> bool {
+        self.timeout.is_some() && device.info().watchdog
+    }
+
+    fn to_model(&self, active: bool, now: Duration) -> models::Watchdog {
+        let timeout = self.timeout.unwrap_or_default();
+        let last_heartbeat = self.last_ack.map(|last| now - last);
+
+        models::Watchdog {
+            active,
+            timeout,
+            last_heartbeat,
+            expired: active && last_heartbeat.is_some_and(|age| age > timeout),
+        }
+    }
+}
+
+/// Host-side heater control state, all times are clock times.
+struct Controller {
+    mode: config::ControlMode,
+    pid: pid::Pid,
+    interval: Duration,
+    /// Temperature requested by the last `SetTemperature` command.
+    setpoint: Option<f32>,
+    next: Duration,
+    duty: Option<f32>,
+}
+
+impl Controller {
+    fn new(mode: config::ControlMode, config: config::Pid) -> Self {
+        Self {
+            mode,
+            pid: pid::Pid::new(pid::Gains::from(&config)),
+            interval: Duration::from_secs(config.interval.max(1)),
+            setpoint: None,
+            next: Duration::ZERO,
+            duty: None,
+        }
+    }
+
+    /// Whether the heater duty of `device` is computed here instead of by its thermostat.
+    fn active<D: Device>(&self, device: &D) -> bool {
+        self.mode == config::ControlMode::Pid && device.info().heater_duty
+    }
+
+    fn to_model(&self, active: bool) -> models::HeaterControl {
+        models::HeaterControl {
+            active,
+            setpoint: self.setpoint.filter(|_| active),
+            duty: self.duty.filter(|_| active),
+        }
+    }
+}
+
+/// Read the temperature from `device`, apply the duty computed for it and schedule the next
+/// control step. Without setpoint or reading the heater is turned off.
+async fn control<D: Device>(
+    device: &mut D,
+    health: &mut Health,
+    controller: &mut Controller,
+    clock: &Clock,
+) {
+    let result = device.read().await;
+    health.record(&result);
+
+    let temperature = result.ok().and_then(|state| state.current_temperature);
+
+    let duty = match (controller.setpoint, temperature) {
+        (Some(setpoint), Some(temperature)) => {
+            controller
+                .pid
+                .update(clock.elapsed(), setpoint, temperature)
+        }
+        _ => {
+            controller.pid.reset();
+            0.0
+        }
+    };
+
+    trace!("Heater duty {} at {:?}", duty, temperature);
+    let result = device.set_heater_duty(duty).await;
+    health.record(&result);
+
+    match result {
+        Ok(()) => controller.duty = Some(duty),
+        Err(err) => warn!("Setting heater duty failed: {}", err),
+    }
+
+    controller.next = clock.elapsed() + controller.interval;
+}
+
+/// Send a heartbeat to `device` and schedule the next one at a third of the timeout.
+async fn heartbeat<D: Device>(
+    device: &mut D,
+    health: &mut Health,
+    watchdog: &mut Watchdog,
+    clock: &Clock,
+) {
+    let Some(timeout) = watchdog.timeout else {
+        return;
+    };
+
+    let result = device.heartbeat(timeout).await;
+    health.record(&result);
+
+    match result {
+        Ok(()) => watchdog.last_ack = Some(clock.elapsed()),
+        Err(err) => warn!("Heartbeat failed: {}", err),
+    }
+
+    watchdog.next = clock.elapsed() + timeout / 3;
+}
+
+/// Try to reconnect `device` and schedule the next attempt with exponential backoff on failure.
+async fn reconnect<D: Device>(device: &mut D, health: &mut Health) {
+    match device.reconnect().await {
+        Ok(()) => {
+            info!("Device reconnected");
+            health.connected = true;
+            health.consecutive_errors = 0;
+            health.reconnects += 1;
+        }
+        Err(err) => {
+            warn!(
+                "Reconnect failed, retrying in {:?}: {}",
+                health.backoff, err
+            );
+            health.last_error = Some(err.to_string());
+            health.next_attempt = Instant::now() + health.backoff;
+            health.backoff = (health.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
 /// Run handler task receiving commands via `rx` and forwards them to the `device`.
-#[instrument]
-pub async fn run<D>(mut device: D, mut rx: mpsc::Receiver<Command>) -> Result<()>
+///
+/// Repeated failures mark the device as disconnected, after which reconnection is attempted with
+/// backoff. While disconnected, reads report a serial problem and writes fail. If the device
+/// supports it, heartbeats are sent on `clock` to keep its watchdog alive. With PID control `mode`,
+/// target temperatures become the setpoint of a controller driving the heater duty every
+/// interval.
+#[instrument(skip(rx, clock))]
+pub async fn run<D>(
+    mut device: D,
+    mut rx: mpsc::Receiver<Command>,
+    clock: Clock,
+    watchdog: config::Watchdog,
+    mode: config::ControlMode,
+    pid: config::Pid,
+) -> Result<()>
 where
     D: Device + std::fmt::Debug,
 {
-    while let Some(command) = rx.recv().await {
+    let mut health = Health::new(device.connected());
+    let mut watchdog = Watchdog::new(watchdog);
+    let mut controller = Controller::new(mode, pid);
+
+    if mode == config::ControlMode::Pid && !device.info().heater_duty {
+        warn!("Device does not support heater duty, falling back to its thermostat");
+    }
+
+    loop {
+        let beat = health.connected && watchdog.active(&device);
+        let regulate = health.connected && controller.active(&device);
+
+        let command = select! {
+            command = rx.recv() => command,
+            _ = sleep_until(health.next_attempt), if !health.connected => {
+                reconnect(&mut device, &mut health).await;
+                continue;
+            }
+            _ = clock.sleep(watchdog.next.saturating_sub(clock.elapsed())), if beat => {
+                heartbeat(&mut device, &mut health, &mut watchdog, &clock).await;
+                continue;
+            }
+            _ = clock.sleep(controller.next.saturating_sub(clock.elapsed())), if regulate => {
+                control(&mut device, &mut health, &mut controller, &clock).await;
+                continue;
+            }
+        };
+
+        let Some(command) = command else {
+            break;
+        };
+
         match command {
             Command::Read { resp } => {
-                let _ = resp.send(device.read().await);
+                if !health.connected {
+                    let _ = resp.send(Ok(models::Device {
+                        serial_problem: true,
+                        ..Default::default()
+                    }));
+                    continue;
+                }
+
+                let result = device.read().await;
+                health.record(&result);
+
+                let _ = resp.send(result.map(|state| models::Device {
+                    target_temperature: if controller.active(&device) {
+                        controller.setpoint
+                    } else {
+                        state.target_temperature
+                    },
+                    serial_problem: health.problem(),
+                    ..state
+                }));
             }
             Command::SetTemperature { temperature, resp } => {
-                let _ = resp.send(device.set_temperature(temperature).await);
+                if !health.connected {
+                    let _ = resp.send(Err(AppError::DeviceDisconnected));
+                    continue;
+                }
+
+                if !device.info().heater {
+                    let _ = resp.send(Err(AppError::Unsupported("heater control")));
+                    continue;
+                }
+
+                if controller.active(&device) {
+                    controller.setpoint = Some(temperature);
+                    controller.next = clock.elapsed();
+                    let _ = resp.send(Ok(()));
+                    continue;
+                }
+
+                let result = device.set_temperature(temperature).await;
+                health.record(&result);
+                let _ = resp.send(result);
+            }
+            Command::SetStirrer { on, resp } => {
+                if !health.connected {
+                    let _ = resp.send(Err(AppError::DeviceDisconnected));
+                    continue;
+                }
+
+                if !device.info().stirrer {
+                    let _ = resp.send(Err(AppError::Unsupported("stirrer")));
+                    continue;
+                }
+
+                let result = device.set_stirrer(on).await;
+                health.record(&result);
+                let _ = resp.send(result);
+            }
+            Command::Health { resp } => {
+                let active = watchdog.active(&device);
+                let watchdog = watchdog.to_model(active, clock.elapsed());
+                let heater_control = controller.to_model(controller.active(&device));
+                let _ = resp.send(Ok(health.to_model(watchdog, heater_control)));
+            }
+            Command::Info { resp } => {
+                let _ = resp.send(Ok(device.info()));
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use comm::emulator::{Config, Emulator};
+    use std::path::Path;
+
+    async fn health(tx: &Sender) -> models::DeviceHealth {
+        let (resp, rx) = oneshot::channel();
+        tx.send(Command::Health { resp }).await.unwrap();
+        rx.await.unwrap().unwrap()
+    }
+
+    /// Poll the device health until `condition` holds or a generous timeout passes.
+    async fn wait_for(tx: &Sender, condition: impl Fn(&models::DeviceHealth) -> bool) {
+        for _ in 0..300 {
+            let health = health(tx).await;
+
+            if condition(&health) {
+                return;
+            }
+
+            let (resp, rx) = oneshot::channel();
+            tx.send(Command::Read { resp }).await.unwrap();
+            let _ = rx.await.unwrap();
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        panic!("device health did not change: {:?}", health(tx).await);
+    }
+
+    /// Spawn an emulator reachable at `link`, so that the device path stays the same across
+    /// emulator restarts.
+    fn spawn_at(link: &Path) -> Emulator {
+        let emulator = Emulator::spawn(Config::default()).unwrap();
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(emulator.path(), link).unwrap();
+        emulator
+    }
+
+    #[tokio::test]
+    async fn reconnects_to_restarted_device() {
+        let link = std::env::temp_dir().join(format!("brewslave-{}", std::process::id()));
+        let emulator = spawn_at(&link);
+
+        let device = brewslave::Brewslave::new(&link, comm::Protocol::Legacy)
+            .await
+            .unwrap();
+        let (tx, rx) = mpsc::channel(32);
+        let task = tokio::spawn(run(
+            device,
+            rx,
+            Clock::new(1.0).unwrap(),
+            config::Watchdog::default(),
+            config::ControlMode::Thermostat,
+            config::Pid::default(),
+        ));
+
+        assert!(health(&tx).await.connected);
+
+        drop(emulator);
+        wait_for(&tx, |health| !health.connected).await;
+
+        let _emulator = spawn_at(&link);
+        wait_for(&tx, |health| health.connected).await;
+
+        let health = health(&tx).await;
+        assert_eq!(health.reconnects, 1);
+
+        task.abort();
+        let _ = std::fs::remove_file(&link);
+    }
+
+    #[tokio::test]
+    async fn starts_disconnected_without_device() {
+        let link = std::env::temp_dir().join(format!("brewslave-missing-{}", std::process::id()));
+        let _ = std::fs::remove_file(&link);
+
+        let device = brewslave::Brewslave::new(&link, comm::Protocol::Legacy)
+            .await
+            .unwrap();
+        let (tx, rx) = mpsc::channel(32);
+        let task = tokio::spawn(run(
+            device,
+            rx,
+            Clock::new(1.0).unwrap(),
+            config::Watchdog::default(),
+            config::ControlMode::Thermostat,
+            config::Pid::default(),
+        ));
+
+        assert!(!health(&tx).await.connected);
+
+        let _emulator = spawn_at(&link);
+        wait_for(&tx, |health| health.connected).await;
+        assert_eq!(health(&tx).await.reconnects, 1);
+
+        task.abort();
+        let _ = std::fs::remove_file(&link);
+    }
+}
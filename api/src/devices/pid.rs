@@ -0,0 +1,318 @@
+//! Host-side PID control of the heater duty.
+//!
+//! The controller works on sensor readings in degree Celsius and clock time in seconds and
+//! outputs a duty between zero and one. The derivative acts on the low-pass filtered measurement,
+//! so that setpoint changes do not kick the output and sensor noise is not amplified, and the
+//! integral is only accumulated while the output is not saturated in the direction of the error.
+
+use crate::clock::Clock;
+use crate::config;
+use crate::devices::Device;
+use crate::{AppError, Result};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Half the distance between the relay switching temperatures during autotune in Kelvin, so that
+/// sensor noise does not toggle the relay.
+const RELAY_HYSTERESIS: f32 = 0.2;
+
+/// Ratio of derivative time and the time constant of the low-pass filter smoothing the derivative
+/// of noisy readings.
+const DERIVATIVE_FILTER: f32 = 4.0;
+
+/// Number of relay oscillations that are measured after the first one settled.
+const AUTOTUNE_CYCLES: usize = 3;
+
+/// Clock time after which autotune gives up.
+const AUTOTUNE_TIMEOUT: Duration = Duration::from_secs(6 * 3600);
+
+/// Proportional, integral and derivative gains.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl From<&config::Pid> for Gains {
+    fn from(pid: &config::Pid) -> Self {
+        Self {
+            kp: pid.kp,
+            ki: pid.ki,
+            kd: pid.kd,
+        }
+    }
+}
+
+/// PID controller state.
+#[derive(Debug)]
+pub struct Pid {
+    gains: Gains,
+    /// Integral term in duty units.
+    integral: f32,
+    /// Filtered rate of change of the temperature in Kelvin per second.
+    derivative: f32,
+    /// Clock time and temperature of the previous update.
+    last: Option<(Duration, f32)>,
+}
+
+impl Pid {
+    pub fn new(gains: Gains) -> Self {
+        Self {
+            gains,
+            integral: 0.0,
+            derivative: 0.0,
+            last: None,
+        }
+    }
+
+    /// Forget the accumulated state, e.g. after readings were missing.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.derivative = 0.0;
+        self.last = None;
+    }
+
+    /// Compute the duty for `temperature` measured at clock time `now` to reach `setpoint`.
+    pub fn update(&mut self, now: Duration, setpoint: f32, temperature: f32) -> f32 {
+        let error = setpoint - temperature;
+
+        let dt = match self.last {
+            Some((last, previous)) if now > last => {
+                let dt = (now - last).as_secs_f32();
+                let filter = if self.gains.kp > 0.0 {
+                    self.gains.kd / self.gains.kp / DERIVATIVE_FILTER
+                } else {
+                    0.0
+                };
+                let rate = (temperature - previous) / dt;
+                self.derivative += (rate - self.derivative) * dt / (dt + filter);
+                dt
+            }
+            _ => 0.0,
+        };
+
+        self.last = Some((now, temperature));
+
+        let proportional = self.gains.kp * error;
+        let damping = self.gains.kd * self.derivative;
+        let output = proportional + self.integral - damping;
+
+        // Anti-windup: stop integrating while saturated and the error pushes further out.
+        let saturated = (output >= 1.0 && error > 0.0) || (output <= 0.0 && error < 0.0);
+
+        if !saturated {
+            self.integral = (self.integral + self.gains.ki * error * dt).clamp(0.0, 1.0);
+        }
+
+        (proportional + self.integral - damping).clamp(0.0, 1.0)
+    }
+}
+
+/// Extremes and switching times of the relay oscillation.
+#[derive(Default)]
+struct Oscillation {
+    /// Clock times at which the relay switched off.
+    switches: Vec<Duration>,
+    maxima: Vec<f32>,
+    minima: Vec<f32>,
+}
+
+/// Determine gains for `device` by relay feedback around `setpoint`.
+///
+/// The heater is switched fully on below and off above the setpoint every `interval` until the
+/// temperature oscillates. The ultimate gain and period derived from amplitude and period of the
+/// oscillation give the gains with the Tyreus-Luyben rule, which is more conservative than
+/// Ziegler-Nichols and suits slow, lagging kettles. If the device has a watchdog, heartbeats with
+/// `watchdog` timeout are sent, so that the heater turns off when autotune is interrupted. The
+/// heater duty is zero afterwards.
+pub async fn autotune<D: Device>(
+    device: &mut D,
+    clock: &Clock,
+    setpoint: f32,
+    interval: Duration,
+    watchdog: Option<Duration>,
+) -> Result<Gains> {
+    if !device.info().heater_duty {
+        return Err(AppError::Unsupported("heater duty"));
+    }
+
+    let result = relay(device, clock, setpoint, interval, watchdog).await;
+
+    if let Err(err) = device.set_heater_duty(0.0).await {
+        warn!("Could not turn the heater off: {}", err);
+    }
+
+    let oscillation = result?;
+
+    // The first period is still influenced by heating up from below.
+    let maxima = &oscillation.maxima[1..];
+    let minima = &oscillation.minima[1..];
+    let switches = &oscillation.switches[1..];
+
+    let amplitude = (mean(maxima) - mean(minima)) / 2.0;
+    let period =
+        (switches[switches.len() - 1] - switches[0]).as_secs_f32() / (switches.len() - 1) as f32;
+
+    if amplitude <= RELAY_HYSTERESIS || period <= 0.0 {
+        return Err(AppError::Autotune("no oscillation around the setpoint"));
+    }
+
+    // Describing function of a relay switching between zero and full duty with hysteresis.
+    let relay_amplitude = 0.5;
+    let ultimate_gain = 4.0 * relay_amplitude
+        / (std::f32::consts::PI * (amplitude.powi(2) - RELAY_HYSTERESIS.powi(2)).sqrt());
+
+    info!("Ultimate gain {ultimate_gain}, period {period} s, amplitude {amplitude} K");
+
+    let kp = ultimate_gain / 3.2;
+    let integral_time = 2.2 * period;
+    let derivative_time = period / 6.3;
+
+    Ok(Gains {
+        kp,
+        ki: kp / integral_time,
+        kd: kp * derivative_time,
+    })
+}
+
+/// Run the relay until enough oscillations were recorded.
+async fn relay<D: Device>(
+    device: &mut D,
+    clock: &Clock,
+    setpoint: f32,
+    interval: Duration,
+    watchdog: Option<Duration>,
+) -> Result<Oscillation> {
+    let watchdog = watchdog.filter(|_| device.info().watchdog);
+    let deadline = clock.elapsed() + AUTOTUNE_TIMEOUT;
+    let mut oscillation = Oscillation::default();
+    let mut heating = true;
+    let mut extreme: Option<f32> = None;
+
+    while oscillation.switches.len() <= AUTOTUNE_CYCLES {
+        if clock.elapsed() > deadline {
+            return Err(AppError::Autotune("timed out"));
+        }
+
+        if let Some(timeout) = watchdog {
+            device.heartbeat(timeout).await?;
+        }
+
+        let temperature = device
+            .read()
+            .await?
+            .current_temperature
+            .ok_or(AppError::Autotune("no temperature reading"))?;
+
+        // Extremes are only tracked after the first switch, i.e. once the setpoint was reached.
+        if !oscillation.switches.is_empty() {
+            extreme = Some(match (extreme, heating) {
+                (Some(extreme), true) => extreme.min(temperature),
+                (Some(extreme), false) => extreme.max(temperature),
+                (None, _) => temperature,
+            });
+        }
+
+        if heating && temperature > setpoint + RELAY_HYSTERESIS {
+            heating = false;
+
+            if let Some(minimum) = extreme.take() {
+                oscillation.minima.push(minimum);
+            }
+
+            oscillation.switches.push(clock.elapsed());
+            extreme = Some(temperature);
+        } else if !heating && temperature < setpoint - RELAY_HYSTERESIS {
+            heating = true;
+
+            if let Some(maximum) = extreme.take() {
+                oscillation.maxima.push(maximum);
+            }
+
+            extreme = Some(temperature);
+        }
+
+        device
+            .set_heater_duty(if heating { 1.0 } else { 0.0 })
+            .await?;
+
+        clock.sleep(interval).await;
+    }
+
+    Ok(oscillation)
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::mock::Mock;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn saturated_output_does_not_wind_up() {
+        let mut pid = Pid::new(Gains {
+            kp: 0.1,
+            ki: 0.01,
+            kd: 0.0,
+        });
+
+        // Far below the setpoint the output is saturated for a long time.
+        for t in 0..1000 {
+            assert_eq!(pid.update(secs(t), 100.0, 20.0), 1.0);
+        }
+
+        // Without anti-windup the integral would keep the heater on above the setpoint.
+        assert_eq!(pid.update(secs(1000), 100.0, 101.0), 0.0);
+    }
+
+    #[test]
+    fn setpoint_change_does_not_kick() {
+        let mut pid = Pid::new(Gains {
+            kp: 0.01,
+            ki: 0.0,
+            kd: 1.0,
+        });
+
+        pid.update(secs(0), 50.0, 40.0);
+        let before = pid.update(secs(1), 50.0, 40.0);
+        let after = pid.update(secs(2), 60.0, 40.0);
+
+        // Only the proportional term follows the setpoint.
+        assert!((after - before - 0.1).abs() < 1e-6, "{before} -> {after}");
+
+        // A rising measurement is damped though.
+        let rising = pid.update(secs(3), 60.0, 41.0);
+        assert!(rising < after - 0.01, "{after} -> {rising}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn autotune_finds_gains() {
+        let clock = Clock::starting_at(Duration::ZERO, 1.0);
+        let simulation = config::Simulation {
+            sensor_noise: 0.0,
+            sensor_lag: 30.0,
+            ..Default::default()
+        };
+        let mut mock = Mock::new(simulation, clock.clone());
+
+        let gains = autotune(&mut mock, &clock, 60.0, secs(5), Some(secs(60)))
+            .await
+            .unwrap();
+
+        for gain in [gains.kp, gains.ki, gains.kd] {
+            assert!(gain.is_finite() && gain > 0.0, "{gains:?}");
+        }
+
+        // The heater is left off.
+        let state = mock.read().await.unwrap();
+        assert!(!state.heater_on);
+    }
+}
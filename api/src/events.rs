@@ -0,0 +1,49 @@
+//! Broadcasts device snapshots and program events to any number of subscribers, so that watching
+//! the brew does not multiply the traffic on the serial line.
+
+use crate::{devices, Result};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tracing::{instrument, warn};
+
+/// Number of events buffered for slow subscribers before they start lagging.
+const CAPACITY: usize = 64;
+
+/// Type alias for the event sender.
+pub type Sender = broadcast::Sender<models::Event>;
+
+/// Create a new event sender. Receivers are created with [`broadcast::Sender::subscribe`].
+pub fn channel() -> Sender {
+    broadcast::channel(CAPACITY).0
+}
+
+/// Read the device state every second and broadcast it via `events`.
+#[instrument(skip_all)]
+pub async fn poll(tx: devices::Sender, events: Sender) -> Result<()> {
+    let mut ticks = interval(Duration::from_secs(1));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticks.tick().await;
+
+        // Nobody is listening, so there is no reason to talk to the device.
+        if events.receiver_count() == 0 {
+            continue;
+        }
+
+        let (resp, rx) = oneshot::channel();
+
+        if tx.send(devices::Command::Read { resp }).await.is_err() {
+            return Ok(());
+        }
+
+        match rx.await? {
+            Ok(device) => {
+                let _ = events.send(models::Event::Device(device));
+            }
+            Err(err) => {
+                warn!("Could not read device state: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Brew log export formats.
+
+use std::fmt::Write;
+
+/// Quote `value` as a CSV field if necessary.
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Find the step executed at `timestamp` and whether it was holding at that time.
+fn step_at(steps: &[models::StepRecord], timestamp: i64) -> Option<(usize, models::Phase)> {
+    steps
+        .iter()
+        .rfind(|step| {
+            step.started_at.is_some_and(|started| started <= timestamp)
+                && step.finished_at.is_none_or(|finished| timestamp < finished)
+        })
+        .map(|step| {
+            let phase = match step.reached_at {
+                Some(reached) if reached <= timestamp => models::Phase::Holding,
+                _ => models::Phase::Heating,
+            };
+
+            (step.position, phase)
+        })
+}
+
+/// Write brew `log` as a single CSV document.
+///
+/// The recipe, step boundaries, additions and deviations are written as `#` comment lines in
+/// front of the measurement table, which carries the step and phase active at each measurement.
+pub fn to_csv(log: &models::BrewLog) -> String {
+    let mut out = String::new();
+    let brew = &log.brew;
+    let recipe = &log.recipe;
+
+    // Writing to a String cannot fail.
+    let _ = writeln!(out, "# brew,{}", brew.id);
+    let _ = writeln!(
+        out,
+        "# state,{}",
+        field(&format!("{:?}", brew.state).to_lowercase())
+    );
+    let _ = writeln!(out, "# started_at,{}", optional(brew.started_at));
+    let _ = writeln!(out, "# finished_at,{}", optional(brew.finished_at));
+    let _ = writeln!(
+        out,
+        "# error,{}",
+        field(brew.error.as_deref().unwrap_or_default())
+    );
+    let _ = writeln!(out, "# recipe,{},{}", recipe.id, field(&recipe.name));
+    let _ = writeln!(out, "# description,{}", field(&recipe.description));
+    let _ = writeln!(
+        out,
+        "# step,type,target_temperature,duration,message,stirrer_on,started_at,reached_at,finished_at"
+    );
+
+    for (position, step) in recipe.steps.iter().enumerate() {
+        let record = log.steps.iter().find(|record| record.position == position);
+
+        let (kind, message) = match step {
+            models::Step::Hold(_) => ("hold", ""),
+            models::Step::Ramp(_) => ("ramp", ""),
+            models::Step::Boil(_) => ("boil", ""),
+            models::Step::Confirm(step) => ("confirm", step.message.as_str()),
+            models::Step::Reminder(step) => ("reminder", step.message.as_str()),
+        };
+
+        let _ = writeln!(
+            out,
+            "# {},{},{},{},{},{},{},{},{}",
+            position,
+            kind,
+            optional(step.target_temperature()),
+            step.duration().as_secs(),
+            field(message),
+            step.stirrer_on(),
+            optional(record.and_then(|record| record.started_at)),
+            optional(record.and_then(|record| record.reached_at)),
+            optional(record.and_then(|record| record.finished_at)),
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# addition,step,position,ingredient,amount,before_end,alerted_at,acknowledged_at"
+    );
+
+    for (index, step) in recipe.steps.iter().enumerate() {
+        for (position, addition) in step.additions().iter().enumerate() {
+            let record = log
+                .additions
+                .iter()
+                .find(|record| record.step == index && record.position == position);
+
+            let _ = writeln!(
+                out,
+                "# addition,{},{},{},{},{},{},{}",
+                index,
+                position,
+                field(&addition.ingredient),
+                field(&addition.amount),
+                addition.before_end.as_secs(),
+                optional(record.and_then(|record| record.alerted_at)),
+                optional(record.and_then(|record| record.acknowledged_at)),
+            );
+        }
+    }
+
+    let _ = writeln!(out, "# deviation,step,started_at,ended_at,max_deviation");
+
+    for deviation in &log.deviations {
+        let _ = writeln!(
+            out,
+            "# deviation,{},{},{},{}",
+            deviation.step,
+            deviation.started_at,
+            optional(deviation.ended_at),
+            deviation.max_deviation,
+        );
+    }
+
+    out.push_str("timestamp,step,phase,temperature,heater_on,stirrer_on\n");
+
+    for measurement in &log.measurements {
+        let (step, phase) = match step_at(&log.steps, measurement.timestamp) {
+            // Confirmation steps are not heating before they are reached but waiting for the user.
+            Some((step, models::Phase::Heating))
+                if matches!(recipe.steps.get(step), Some(models::Step::Confirm(_))) =>
+            {
+                (step.to_string(), "waiting")
+            }
+            Some((step, models::Phase::Heating)) => (step.to_string(), "heating"),
+            Some((step, models::Phase::Holding)) => (step.to_string(), "holding"),
+            Some((step, models::Phase::Waiting)) => (step.to_string(), "waiting"),
+            None => (String::new(), ""),
+        };
+
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            measurement.timestamp,
+            step,
+            phase,
+            optional(measurement.temperature),
+            optional(measurement.heater_on),
+            optional(measurement.stirrer_on),
+        );
+    }
+
+    out
+}
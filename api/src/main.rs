@@ -2,22 +2,31 @@
 
 use axum::http::header::InvalidHeaderValue;
 use clap::Parser;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 use tokio::try_join;
 use tracing::error;
 
 mod api;
+mod clock;
 mod config;
 mod db;
 mod devices;
+mod events;
+mod export;
 mod program;
+mod safety;
+mod validation;
 
 #[derive(Parser)]
 struct Opt {
     /// Use a mock device instead of the real Arduino Brewslave
     #[clap(long)]
     use_mock: bool,
+    /// Determine PID gains by relay feedback around the given temperature, print them and exit
+    #[clap(long, value_name = "TEMPERATURE")]
+    autotune: Option<f32>,
 }
 
 /// Possible API errors.
@@ -25,14 +34,28 @@ struct Opt {
 pub enum AppError {
     #[error("Address parse failed: {0}")]
     AddrParseError(#[from] std::net::AddrParseError),
+    #[error("Autotune failed: {0}")]
+    Autotune(&'static str),
+    #[error("Brew was aborted")]
+    BrewAborted,
+    #[error("Brew {0} is not running")]
+    BrewNotRunning(models::BrewId),
+    #[error("Brew {0} is not interrupted")]
+    BrewNotInterrupted(models::BrewId),
     #[error("Brew is ongoing")]
     BrewOngoing,
     #[error("Serial communication error: {0}")]
     CommError(#[from] comm::Error),
+    #[error("Brew {0} is not waiting for a confirmation")]
+    NotAwaitingConfirmation(models::BrewId),
+    #[error("Device is disconnected")]
+    DeviceDisconnected,
     #[error("Could not read configuration: {0}")]
     ConfigurationError(#[from] toml::de::Error),
     #[error("Internal error: {0}")]
     RecvError(#[from] oneshot::error::RecvError),
+    #[error("Recipe {0} is used by brews")]
+    RecipeInUse(models::RecipeId),
     #[error("Invalid header: {0}")]
     InvalidHeader(#[from] InvalidHeaderValue),
     #[error("IO error")]
@@ -41,13 +64,40 @@ pub enum AppError {
     ParseError(#[from] serde_json::Error),
     #[error("Database problem: {0}")]
     SqlError(#[from] sqlx::Error),
+    #[error("Safety limit violated: {0}")]
+    SafetyViolation(String),
+    #[error("Device does not support {0}")]
+    Unsupported(&'static str),
     #[error("System time error: {0}")]
     SystemTimeError(#[from] std::time::SystemTimeError),
+    #[error("Validation failed")]
+    Validation(models::ValidationErrors),
 }
 
 /// API result type.
 pub type Result<T, E = AppError> = std::result::Result<T, E>;
 
+/// Tune the PID gains of `device` around `setpoint` and print them as `[pid]` section.
+async fn autotune<D: devices::Device>(
+    mut device: D,
+    clock: &clock::Clock,
+    setpoint: f32,
+    pid: &config::Pid,
+    watchdog: &config::Watchdog,
+) -> Result<()> {
+    let interval = Duration::from_secs(pid.interval.max(1));
+    let timeout = (watchdog.timeout > 0).then(|| Duration::from_secs(watchdog.timeout));
+    let gains = devices::pid::autotune(&mut device, clock, setpoint, interval, timeout).await?;
+
+    println!("[pid]");
+    println!("kp = {}", gains.kp);
+    println!("ki = {}", gains.ki);
+    println!("kd = {}", gains.kd);
+    println!("interval = {}", pid.interval);
+
+    Ok(())
+}
+
 async fn try_main() -> Result<()> {
     let opts = Opt::parse();
     let config = config::Config::new()?;
@@ -55,19 +105,76 @@ async fn try_main() -> Result<()> {
     let (device_tx, device_rx) = mpsc::channel(32);
     let (brew_tx, brew_rx) = mpsc::channel(32);
 
-    let db = db::Database::new(config.database).await?;
-    let brew_future = program::run(device_tx.clone(), brew_rx, db.clone());
-    let state = api::AppState::new(db, device_tx, brew_tx).await?;
+    let events_tx = events::channel();
+
+    // The simulation speedup accelerates all program time, not only the simulated kettle.
+    let speedup = if opts.use_mock {
+        config.simulation.speedup
+    } else {
+        1.0
+    };
+
+    let clock = clock::Clock::new(speedup)?;
+
+    if let Some(setpoint) = opts.autotune {
+        return if opts.use_mock {
+            let device = devices::mock::Mock::new(config.simulation, clock.clone());
+            autotune(device, &clock, setpoint, &config.pid, &config.watchdog).await
+        } else {
+            let device =
+                devices::brewslave::Brewslave::new(&config.device, config.protocol).await?;
+            autotune(device, &clock, setpoint, &config.pid, &config.watchdog).await
+        };
+    }
+
+    let db = db::Database::new(config.database, clock.clone()).await?;
+    let safe_temperature = config.safety.safe_temperature;
+    let brew_future = program::run(
+        device_tx.clone(),
+        brew_rx,
+        db.clone(),
+        events_tx.clone(),
+        clock.clone(),
+        program::Settings {
+            safety: config.safety,
+            boil: config.boil,
+            tolerance: config.tolerance,
+        },
+    );
+    let poll_future = events::poll(device_tx.clone(), events_tx.clone());
+    let state = api::AppState::new(
+        db,
+        device_tx,
+        brew_tx,
+        events_tx,
+        config.limits,
+        safe_temperature,
+    )
+    .await?;
     let server_future = api::run(state);
 
     if opts.use_mock {
-        let device = devices::mock::Mock::new();
-        let comm_future = devices::run(device, device_rx);
-        try_join!(server_future, comm_future, brew_future)?;
+        let device = devices::mock::Mock::new(config.simulation, clock.clone());
+        let comm_future = devices::run(
+            device,
+            device_rx,
+            clock,
+            config.watchdog,
+            config.control,
+            config.pid,
+        );
+        try_join!(server_future, comm_future, brew_future, poll_future)?;
     } else {
-        let device = devices::brewslave::Brewslave::new(&config.device)?;
-        let comm_future = devices::run(device, device_rx);
-        try_join!(server_future, comm_future, brew_future)?;
+        let device = devices::brewslave::Brewslave::new(&config.device, config.protocol).await?;
+        let comm_future = devices::run(
+            device,
+            device_rx,
+            clock,
+            config.watchdog,
+            config.control,
+            config.pid,
+        );
+        try_join!(server_future, comm_future, brew_future, poll_future)?;
     }
 
     Ok(())
@@ -1,28 +1,199 @@
 //! Executes a brew "program", i.e. set target temperatures and wait until they are reached and
 //! then wait more until the required duration has passed.
 
-use crate::{devices, AppError, Result};
+use crate::clock::Clock;
+use crate::config::{Boil, Safety, Tolerance};
+use crate::safety::Supervisor;
+use crate::{devices, events, AppError, Result};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot};
-use tokio::time::{sleep, Duration};
+use tokio::select;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio::time::Duration;
 use tracing::{error, info, instrument, warn};
 
+/// Interval between device readings while a program runs.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 /// Used by the caller to get a result back from a command.
 type Responder<T> = oneshot::Sender<Result<T>>;
 
 /// Commands to send to the program channel.
 pub enum Command {
+    /// Record a brew of `recipe` and run it unless a brew is ongoing.
     Start {
+        recipe: models::Recipe,
+        resp: Responder<models::BrewId>,
+    },
+    Pause {
+        id: models::BrewId,
+        resp: Responder<()>,
+    },
+    Resume {
+        id: models::BrewId,
+        resp: Responder<()>,
+    },
+    Abort {
+        id: models::BrewId,
+        resp: Responder<()>,
+    },
+    Confirm {
+        id: models::BrewId,
+        resp: Responder<()>,
+    },
+    Status {
+        resp: Responder<Option<models::BrewStatus>>,
+    },
+    Recover {
         id: models::BrewId,
-        steps: Vec<models::Step>,
+        action: models::RecoveryAction,
         resp: Responder<()>,
     },
 }
 
+/// Control state of a running program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Control {
+    Run,
+    Pause,
+    Abort,
+}
+
+/// Handles to the most recently started program.
+struct Current {
+    id: models::BrewId,
+    control: watch::Sender<Control>,
+    /// Notified when the user confirms the step the program waits at.
+    confirmation: Arc<Notify>,
+}
+
+/// Configuration of program execution.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub safety: Safety,
+    pub boil: Boil,
+    /// Default step temperature tolerance.
+    pub tolerance: Tolerance,
+}
+
+/// Condition ending the heating phase of a step.
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    /// Within the tolerance band around the temperature.
+    Exactly(f32),
+    /// At or above the temperature, e.g. when boiling.
+    AtLeast(f32),
+}
+
+impl Target {
+    fn temperature(self) -> f32 {
+        match self {
+            Target::Exactly(temperature) | Target::AtLeast(temperature) => temperature,
+        }
+    }
+
+    /// Distance of `current` from the target in Kelvin.
+    fn deviation(self, current: f32) -> f32 {
+        match self {
+            Target::Exactly(temperature) => (current - temperature).abs(),
+            Target::AtLeast(temperature) => (temperature - current).max(0.0),
+        }
+    }
+
+    fn reached(self, current: f32, band: f32) -> bool {
+        match self {
+            Target::Exactly(_) => self.deviation(current) < band,
+            Target::AtLeast(temperature) => current >= temperature,
+        }
+    }
+
+    fn in_band(self, current: f32, band: f32) -> bool {
+        self.deviation(current) < band
+    }
+}
+
+/// Temperature excursion out of the tolerance band while holding.
+#[derive(Debug)]
+struct Excursion {
+    /// Identifier of the recorded deviation.
+    record: i64,
+    max_deviation: f32,
+}
+
 /// Type alias for the command sender.
 pub type Sender = mpsc::Sender<Command>;
 
+/// Step a program starts at.
+#[derive(Clone, Copy, Debug, Default)]
+struct Entry {
+    step: usize,
+    /// Time the step was already held.
+    held: Duration,
+    /// Whether the step was started before an interruption and is continued.
+    continued: bool,
+}
+
+/// Progress of the running program as published by the program task.
+#[derive(Clone, Debug)]
+struct Progress {
+    id: models::BrewId,
+    durations: Vec<Duration>,
+    step: usize,
+    phase: models::Phase,
+    /// Hold time accumulated before `since`.
+    held: Duration,
+    /// Clock time at the start of the current, uninterrupted hold period.
+    since: Option<Duration>,
+    out_of_band: bool,
+}
+
+impl Progress {
+    fn new(id: models::BrewId, steps: &[models::Step]) -> Self {
+        Self {
+            id,
+            durations: steps.iter().map(models::Step::duration).collect(),
+            step: 0,
+            phase: models::Phase::Heating,
+            held: Duration::ZERO,
+            since: None,
+            out_of_band: false,
+        }
+    }
+
+    /// Convert to the API status at clock time `now`. Heating phases are not included in the
+    /// remaining time estimate.
+    fn status(&self, paused: bool, now: Duration) -> models::BrewStatus {
+        let held = self.held + self.since.map_or(Duration::ZERO, |since| now - since);
+        let current = self.durations.get(self.step).copied().unwrap_or_default();
+        let upcoming = self.durations.iter().skip(self.step + 1).sum::<Duration>();
+
+        models::BrewStatus {
+            id: self.id,
+            step: self.step,
+            phase: self.phase,
+            paused,
+            held,
+            remaining: current.saturating_sub(held) + upcoming,
+            out_of_band: self.out_of_band,
+        }
+    }
+}
+
+/// Shared sender to publish the progress of the running program.
+type ProgressSender = Arc<watch::Sender<Option<Progress>>>;
+
+/// Update the published progress with `f`.
+fn publish<F>(progress: &ProgressSender, f: F)
+where
+    F: FnOnce(&mut Progress),
+{
+    progress.send_modify(|progress| {
+        if let Some(progress) = progress {
+            f(progress);
+        }
+    });
+}
+
 #[instrument(skip(tx))]
 async fn set_temperature(tx: devices::Sender, temperature: f32) -> Result<()> {
     let (resp, rx) = oneshot::channel();
@@ -32,105 +203,1132 @@ async fn set_temperature(tx: devices::Sender, temperature: f32) -> Result<()> {
 }
 
 #[instrument(skip(tx))]
-async fn read_temperature(tx: devices::Sender) -> Result<Option<f32>> {
+async fn set_stirrer(tx: devices::Sender, on: bool) -> Result<()> {
     let (resp, rx) = oneshot::channel();
-    let command = devices::Command::Read { resp };
+    let command = devices::Command::SetStirrer { on, resp };
     let _ = tx.send(command).await;
-    Ok(rx.await??.current_temperature)
+    rx.await?
 }
 
 #[instrument(skip(tx))]
-async fn wait_for(
+async fn read_state(tx: devices::Sender) -> Result<models::Device> {
+    let (resp, rx) = oneshot::channel();
+    let command = devices::Command::Read { resp };
+    let _ = tx.send(command).await;
+    rx.await?
+}
+
+/// A single program execution and the handles it needs.
+struct Program {
     id: models::BrewId,
     tx: devices::Sender,
-    temperature: f32,
     db: crate::db::Database,
-) -> Result<()> {
-    loop {
-        let cloned = tx.clone();
+    control: watch::Receiver<Control>,
+    progress: ProgressSender,
+    events: events::Sender,
+    clock: Clock,
+    safety: Supervisor,
+    boil: Boil,
+    tolerance: Tolerance,
+    confirmation: Arc<Notify>,
+}
 
-        match read_temperature(cloned).await? {
-            Some(current) => {
-                db.add_sample(id, current).await?;
+impl Program {
+    /// Broadcast a program `event`, ignoring if nobody is listening.
+    fn emit(&self, event: models::Event) {
+        let _ = self.events.send(event);
+    }
+
+    /// Read the device, record the sample and check it against the safety limits. Return the
+    /// current temperature if the device reported one. A failed read counts as missing reading, so
+    /// that the safety limits decide when a flaky device aborts the brew.
+    async fn sample(&mut self) -> Result<Option<f32>> {
+        let temperature = match read_state(self.tx.clone()).await {
+            Ok(device) if device.current_temperature.is_some() => {
+                self.db.add_sample(self.id, &device).await?;
+                device.current_temperature
+            }
+            Ok(_) => {
+                warn!("No temperature received from the device");
+                None
+            }
+            Err(err) => {
+                warn!("Could not read the device: {}", err);
+                None
+            }
+        };
 
-                if (current - temperature).abs() < 0.5 {
-                    info!("Reached {:.2}C", current);
-                    break;
+        self.safety.check(self.clock.elapsed(), temperature)?;
+
+        Ok(temperature)
+    }
+
+    /// Return once the program is allowed to run and fail if aborted. The heater keeps its target
+    /// while paused, so the device is still sampled and checked against the safety limits.
+    async fn wait_while_paused(&mut self) -> Result<()> {
+        loop {
+            let current = *self.control.borrow_and_update();
+
+            match current {
+                Control::Run => return Ok(()),
+                Control::Abort => return Err(AppError::BrewAborted),
+                Control::Pause => {
+                    select! {
+                        _ = self.clock.sleep(POLL_INTERVAL) => {
+                            self.sample().await?;
+                        }
+                        changed = self.control.changed() => {
+                            if changed.is_err() {
+                                return Err(AppError::BrewAborted);
+                            }
+                        }
+                    }
                 }
             }
+        }
+    }
+
+    /// Persist the published progress, so that the brew can be recovered after an interruption.
+    async fn checkpoint(&self) -> Result<()> {
+        let now = self.clock.elapsed();
+
+        let status = self
+            .progress
+            .borrow()
+            .as_ref()
+            .map(|progress| progress.status(false, now));
+
+        if let Some(status) = status {
+            let progress = models::BrewProgress {
+                step: status.step,
+                phase: status.phase,
+                held: status.held,
+            };
+
+            self.db.save_progress(self.id, progress).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Announce the `additions` of step `index` that are due with `left` hold time and were not
+    /// `alerted` yet. Return the time until the next addition is due.
+    async fn announce_additions(
+        &mut self,
+        index: usize,
+        additions: &[models::Addition],
+        alerted: &mut [bool],
+        left: Duration,
+    ) -> Result<Option<Duration>> {
+        let mut next: Option<Duration> = None;
+
+        for (position, addition) in additions.iter().enumerate() {
+            if alerted[position] {
+                continue;
+            }
+
+            if addition.before_end >= left {
+                info!("Add {} of {} now", addition.amount, addition.ingredient);
+                self.db.alert_addition(self.id, index, position).await?;
+                alerted[position] = true;
+
+                self.emit(models::Event::AdditionDue {
+                    id: self.id,
+                    step: index,
+                    addition: position,
+                    ingredient: addition.ingredient.clone(),
+                    amount: addition.amount.clone(),
+                });
+            } else {
+                let until = left - addition.before_end;
+                next = Some(next.map_or(until, |next| next.min(until)));
+            }
+        }
+
+        Ok(next)
+    }
+
+    /// Check `temperature` against the `band` around `target` while holding step `index` and
+    /// record excursions out of it. Return whether the temperature is in band.
+    async fn track_band(
+        &mut self,
+        index: usize,
+        target: Target,
+        band: f32,
+        temperature: Option<f32>,
+        excursion: &mut Option<Excursion>,
+    ) -> Result<bool> {
+        let Some(temperature) = temperature else {
+            return Ok(excursion.is_none());
+        };
+
+        if target.in_band(temperature, band) {
+            if excursion.is_some() {
+                self.end_excursion(excursion).await?;
+
+                self.emit(models::Event::BackInBand {
+                    id: self.id,
+                    step: index,
+                    temperature,
+                });
+            }
+
+            return Ok(true);
+        }
+
+        let deviation = target.deviation(temperature);
+
+        match excursion {
+            Some(excursion) if deviation > excursion.max_deviation => {
+                self.db
+                    .update_deviation(excursion.record, deviation)
+                    .await?;
+                excursion.max_deviation = deviation;
+            }
+            Some(_) => {}
             None => {
-                // TODO: return after a few tries.
-                warn!("No temperature received from the device");
+                warn!("{:.2}C is out of band, {:.2}K off", temperature, deviation);
+
+                let record = self.db.start_deviation(self.id, index, deviation).await?;
+
+                *excursion = Some(Excursion {
+                    record,
+                    max_deviation: deviation,
+                });
+
+                publish(&self.progress, |progress| progress.out_of_band = true);
+
+                self.emit(models::Event::OutOfBand {
+                    id: self.id,
+                    step: index,
+                    temperature,
+                });
             }
         }
 
-        sleep(Duration::from_secs(5)).await;
+        Ok(false)
     }
 
-    Ok(())
+    /// Record the end of an ongoing `excursion`.
+    async fn end_excursion(&mut self, excursion: &mut Option<Excursion>) -> Result<()> {
+        if let Some(excursion) = excursion.take() {
+            info!("Back in band after {:.2}K", excursion.max_deviation);
+            self.db.end_deviation(excursion.record).await?;
+            publish(&self.progress, |progress| progress.out_of_band = false);
+        }
+
+        Ok(())
+    }
+
+    /// Keep sampling the device until the temperature is back in the `band` around `target`.
+    async fn wait_in_band(
+        &mut self,
+        index: usize,
+        target: Target,
+        band: f32,
+        excursion: &mut Option<Excursion>,
+    ) -> Result<()> {
+        loop {
+            self.wait_while_paused().await?;
+
+            select! {
+                _ = self.clock.sleep(POLL_INTERVAL) => {
+                    let temperature = self.sample().await?;
+
+                    if self.track_band(index, target, band, temperature, excursion).await? {
+                        return Ok(());
+                    }
+                }
+                changed = self.control.changed() => {
+                    if changed.is_err() {
+                        return Err(AppError::BrewAborted);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hold step `index` for the rest of `duration` after `held` while sampling the device,
+    /// announcing due `additions` and waiting while paused, so that the remaining time is kept on
+    /// pause. If `band` is given, the temperature is tracked against the target with the
+    /// tolerance and the hold clock stops while out of band if the tolerance says so.
+    async fn hold(
+        &mut self,
+        index: usize,
+        duration: Duration,
+        held: Duration,
+        additions: &[models::Addition],
+        band: Option<(Target, Tolerance)>,
+    ) -> Result<()> {
+        let mut remaining = duration.saturating_sub(held);
+        let mut alerted = vec![false; additions.len()];
+        let mut excursion = None;
+
+        // Additions announced before an interruption are not announced again.
+        for position in self.db.alerted_additions(self.id, index).await? {
+            if let Some(alerted) = alerted.get_mut(position) {
+                *alerted = true;
+            }
+        }
+
+        loop {
+            self.wait_while_paused().await?;
+            let start = self.clock.elapsed();
+
+            publish(&self.progress, |progress| {
+                progress.phase = models::Phase::Holding;
+                progress.held = duration - remaining;
+                progress.since = Some(start);
+            });
+
+            self.checkpoint().await?;
+
+            loop {
+                let left = remaining.saturating_sub(self.clock.elapsed() - start);
+
+                let next = self
+                    .announce_additions(index, additions, &mut alerted, left)
+                    .await?;
+
+                if left.is_zero() {
+                    self.end_excursion(&mut excursion).await?;
+                    return Ok(());
+                }
+
+                let wait = next.map_or(left, |next| next.min(left)).min(POLL_INTERVAL);
+
+                select! {
+                    _ = self.clock.sleep(wait) => {
+                        let temperature = self.sample().await?;
+
+                        if let Some((target, tolerance)) = &band {
+                            let (target, width) = (*target, tolerance.band);
+
+                            let in_band = self
+                                .track_band(index, target, width, temperature, &mut excursion)
+                                .await?;
+
+                            if !in_band && tolerance.out_of_band == models::OutOfBand::Pause {
+                                remaining = remaining.saturating_sub(self.clock.elapsed() - start);
+
+                                publish(&self.progress, |progress| {
+                                    progress.held = duration - remaining;
+                                    progress.since = None;
+                                });
+
+                                self.checkpoint().await?;
+
+                                info!("Out of band, {:?} of hold time remaining", remaining);
+                                self.wait_in_band(index, target, width, &mut excursion).await?;
+                                break;
+                            }
+                        }
+
+                        self.checkpoint().await?;
+                    }
+                    changed = self.control.changed() => {
+                        if changed.is_err() {
+                            return Err(AppError::BrewAborted);
+                        }
+
+                        remaining = remaining.saturating_sub(self.clock.elapsed() - start);
+
+                        publish(&self.progress, |progress| {
+                            progress.held = duration - remaining;
+                            progress.since = None;
+                        });
+
+                        self.checkpoint().await?;
+
+                        info!("Program control changed, {:?} of hold time remaining", remaining);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Poll the device until `target` is reached within the `tolerance` band for the required
+    /// number of consecutive samples and return the last temperature.
+    #[instrument(skip(self))]
+    async fn wait_for(&mut self, target: Target, tolerance: &Tolerance) -> Result<f32> {
+        self.safety.reset_progress();
+        let mut settled = 0;
+
+        loop {
+            self.wait_while_paused().await?;
+
+            if let Some(current) = self.sample().await? {
+                if target.reached(current, tolerance.band) {
+                    settled += 1;
+
+                    if settled >= tolerance.settle_samples {
+                        info!("Reached {:.2}C", current);
+                        return Ok(current);
+                    }
+                } else {
+                    settled = 0;
+
+                    self.safety.check_progress(
+                        self.clock.elapsed(),
+                        current,
+                        target.temperature(),
+                    )?;
+                }
+            }
+
+            select! {
+                _ = self.clock.sleep(POLL_INTERVAL) => {}
+                changed = self.control.changed() => {
+                    if changed.is_err() {
+                        return Err(AppError::BrewAborted);
+                    }
+
+                    // Time spent paused does not count against heating progress.
+                    self.safety.reset_progress();
+                }
+            }
+        }
+    }
+
+    /// Wait until `target` is reached with `tolerance` and record that the hold of step `index`
+    /// starts.
+    async fn heat(&mut self, index: usize, target: Target, tolerance: &Tolerance) -> Result<()> {
+        let temperature = self.wait_for(target, tolerance).await?;
+        self.db.reach_brew_step(self.id, index).await?;
+
+        self.emit(models::Event::TargetReached {
+            id: self.id,
+            step: index,
+            temperature,
+        });
+
+        Ok(())
+    }
+
+    /// Move the device target linearly from the current temperature to `target` at `rate` degrees
+    /// per minute. Time spent paused does not advance the ramp.
+    #[instrument(skip(self))]
+    async fn ramp(&mut self, target: f32, rate: f32) -> Result<()> {
+        let start = loop {
+            self.wait_while_paused().await?;
+
+            if let Some(current) = self.sample().await? {
+                break current;
+            }
+
+            self.clock.sleep(POLL_INTERVAL).await;
+        };
+
+        let mut ramped = Duration::ZERO;
+
+        loop {
+            self.wait_while_paused().await?;
+
+            let delta = rate * ramped.as_secs_f32() / 60.0;
+
+            let (setpoint, done) = if start <= target {
+                (start + delta, start + delta >= target)
+            } else {
+                (start - delta, start - delta <= target)
+            };
+
+            let setpoint = if done { target } else { setpoint };
+            set_temperature(self.tx.clone(), setpoint).await?;
+
+            if done {
+                return Ok(());
+            }
+
+            let since = self.clock.elapsed();
+
+            select! {
+                _ = self.clock.sleep(POLL_INTERVAL) => {
+                    self.sample().await?;
+                }
+                changed = self.control.changed() => {
+                    if changed.is_err() {
+                        return Err(AppError::BrewAborted);
+                    }
+                }
+            }
+
+            ramped += self.clock.elapsed() - since;
+        }
+    }
+
+    /// Keep sampling the device until the user confirms step `index` showing `message`.
+    #[instrument(skip(self))]
+    async fn confirm(&mut self, index: usize, message: &str) -> Result<()> {
+        publish(&self.progress, |progress| {
+            progress.phase = models::Phase::Waiting;
+        });
+
+        self.checkpoint().await?;
+
+        self.emit(models::Event::ConfirmationRequired {
+            id: self.id,
+            step: index,
+            message: message.to_string(),
+        });
+
+        loop {
+            self.wait_while_paused().await?;
+
+            select! {
+                _ = self.confirmation.notified() => {
+                    info!("Step confirmed");
+                    return Ok(());
+                }
+                _ = self.clock.sleep(POLL_INTERVAL) => {
+                    self.sample().await?;
+                }
+                changed = self.control.changed() => {
+                    if changed.is_err() {
+                        return Err(AppError::BrewAborted);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the given program `steps` from `entry` until completion.
+    #[instrument(skip_all)]
+    async fn run(&mut self, steps: Vec<models::Step>, entry: Entry) -> Result<()> {
+        let id = self.id;
+
+        for (index, step) in steps.into_iter().enumerate().skip(entry.step) {
+            let (held, continued) = if index == entry.step {
+                (entry.held, entry.continued)
+            } else {
+                (Duration::ZERO, false)
+            };
+
+            self.wait_while_paused().await?;
+
+            publish(&self.progress, |progress| {
+                progress.step = index;
+                progress.phase = models::Phase::Heating;
+                progress.held = held;
+                progress.since = None;
+            });
+
+            if !continued {
+                self.db.start_brew_step(id, index).await?;
+            }
+
+            self.checkpoint().await?;
+
+            self.emit(models::Event::StepStarted {
+                id,
+                step: index,
+                target_temperature: step.target_temperature(),
+            });
+
+            match set_stirrer(self.tx.clone(), step.stirrer_on()).await {
+                Err(AppError::Unsupported(_)) if !step.stirrer_on() => {}
+                Err(AppError::Unsupported(feature)) => {
+                    warn!("Device does not support {}, continuing without", feature);
+                }
+                result => result?,
+            }
+
+            let tolerance = self.tolerance.with(step.tolerance());
+
+            match step {
+                models::Step::Hold(step) => {
+                    info!(
+                        "Set target temperature to {}C and wait",
+                        step.target_temperature
+                    );
+                    let target = Target::Exactly(step.target_temperature);
+                    set_temperature(self.tx.clone(), step.target_temperature).await?;
+                    self.heat(index, target, &tolerance).await?;
+
+                    info!("Target temperature reached, waiting {:?}", step.duration);
+                    let band = Some((target, tolerance));
+                    self.hold(index, step.duration, held, &step.additions, band)
+                        .await?;
+                }
+                models::Step::Ramp(step) => {
+                    info!("Ramp to {}C at {}C/min", step.target_temperature, step.rate);
+                    self.ramp(step.target_temperature, step.rate).await?;
+                    let target = Target::Exactly(step.target_temperature);
+                    self.heat(index, target, &tolerance).await?;
+                }
+                models::Step::Boil(step) => {
+                    info!("Heat until boiling at {}C", self.boil.temperature);
+                    let target = Target::AtLeast(self.boil.temperature);
+                    set_temperature(self.tx.clone(), self.boil.target).await?;
+                    self.heat(index, target, &tolerance).await?;
+
+                    info!("Boiling, waiting {:?}", step.duration);
+                    let band = Some((target, tolerance));
+                    self.hold(index, step.duration, held, &step.additions, band)
+                        .await?;
+
+                    // Steps without own target, e.g. a whirlpool reminder, must not keep boiling.
+                    let safe_temperature = self.safety.safe_temperature();
+                    info!("Boil finished, setting {}C", safe_temperature);
+                    set_temperature(self.tx.clone(), safe_temperature).await?;
+                }
+                models::Step::Confirm(step) => {
+                    info!("Waiting for confirmation of {:?}", step.message);
+                    self.confirm(index, &step.message).await?;
+                    self.db.reach_brew_step(id, index).await?;
+                }
+                models::Step::Reminder(step) => {
+                    self.db.reach_brew_step(id, index).await?;
+                    info!("Reminding of {:?} in {:?}", step.message, step.duration);
+                    self.hold(index, step.duration, held, &[], None).await?;
+
+                    self.emit(models::Event::Reminder {
+                        id,
+                        step: index,
+                        message: step.message,
+                    });
+                }
+            }
+
+            self.db.finish_brew_step(id, index).await?;
+        }
+
+        self.emit(models::Event::BrewFinished { id });
+
+        Ok(())
+    }
 }
 
-/// Run the given program `steps` until completion.
-#[instrument(skip_all)]
-async fn run_program(
+/// Send `new` control state to the running brew `id`.
+fn control(
+    current: &Option<Current>,
+    running: &AtomicBool,
     id: models::BrewId,
-    tx: devices::Sender,
-    steps: Vec<models::Step>,
-    db: crate::db::Database,
+    new: Control,
 ) -> Result<()> {
-    for step in steps {
-        info!(
-            "Set target temperature to {}C and wait",
-            step.target_temperature
-        );
-        set_temperature(tx.clone(), step.target_temperature).await?;
-        wait_for(id, tx.clone(), step.target_temperature, db.clone()).await?;
+    match current {
+        Some(current) if current.id == id && running.load(Ordering::Relaxed) => {
+            info!("Setting program control to {:?}", new);
+            current.control.send_replace(new);
+            Ok(())
+        }
+        _ => {
+            warn!("Brew {} is not running", id);
+            Err(AppError::BrewNotRunning(id))
+        }
+    }
+}
+
+/// Prepare recovery of the interrupted brew `id` with `action`. Return the steps and entry to run
+/// or `None` if the brew was aborted.
+async fn recover(
+    db: &crate::db::Database,
+    tx: devices::Sender,
+    safety: &Safety,
+    id: models::BrewId,
+    action: models::RecoveryAction,
+) -> Result<Option<(Vec<models::Step>, Entry)>> {
+    let brew = db.brew(id).await?;
 
-        info!("Target temperature reached, waiting {:?}", step.duration);
-        sleep(step.duration).await;
+    if brew.state != models::BrewState::Interrupted {
+        return Err(AppError::BrewNotInterrupted(id));
     }
 
-    Ok(())
+    let step = brew.progress.map_or(0, |progress| progress.step);
+
+    let entry = match action {
+        models::RecoveryAction::Resume => Entry {
+            step,
+            held: brew
+                .progress
+                .map_or(Duration::ZERO, |progress| progress.held),
+            continued: brew.progress.is_some(),
+        },
+        models::RecoveryAction::RestartStep => Entry {
+            step,
+            held: Duration::ZERO,
+            continued: false,
+        },
+        models::RecoveryAction::Abort => {
+            info!("Aborting interrupted brew {}", id);
+
+            if let Err(err) = set_temperature(tx, safety.safe_temperature).await {
+                error!("{}", err);
+            }
+
+            db.finish_brew(id, models::BrewState::Aborted, None).await?;
+            return Ok(None);
+        }
+    };
+
+    info!("Recovering brew {} at step {} with {:?}", id, step, action);
+
+    let recipe = db.brew_recipe(id).await?;
+    db.continue_brew(id).await?;
+
+    Ok(Some((recipe.steps, entry)))
 }
 
 /// Run handler task receiving brew commands via `rx` and use `tx` to send device commands.
+/// Program events are broadcast via `events`, all waiting is done on `clock` and steps are executed
+/// according to `settings`.
 #[instrument(skip_all)]
 pub async fn run(
     tx: devices::Sender,
     mut rx: mpsc::Receiver<Command>,
     db: crate::db::Database,
+    events: events::Sender,
+    clock: Clock,
+    settings: Settings,
 ) -> Result<()> {
     let running = Arc::new(AtomicBool::new(false));
+    let mut current: Option<Current> = None;
+    let (progress_tx, progress_rx) = watch::channel::<Option<Progress>>(None);
+    let progress_tx = Arc::new(progress_tx);
+
+    let interrupted = db.interrupt_running_brews().await?;
+
+    if interrupted > 0 {
+        warn!("{} interrupted brews can be recovered", interrupted);
+    }
 
     while let Some(command) = rx.recv().await {
         let cloned = tx.clone();
 
-        match command {
-            Command::Start { id, steps, resp } => {
+        let (id, steps, entry) = match command {
+            Command::Start { recipe, resp } => {
                 if running.load(Ordering::Relaxed) {
                     warn!("Brew is ongoing");
                     let _ = resp.send(Err(AppError::BrewOngoing));
                     continue;
                 }
 
-                running.store(true, Ordering::Relaxed);
-
-                let db = db.clone();
+                // The brew is only recorded once it is certain to run.
+                match db.add_brew(&recipe).await {
+                    Ok(brew) => {
+                        let _ = resp.send(Ok(brew.id));
+                        (brew.id, recipe.steps, Entry::default())
+                    }
+                    Err(err) => {
+                        let _ = resp.send(Err(err));
+                        continue;
+                    }
+                }
+            }
+            Command::Recover { id, action, resp } => {
+                if running.load(Ordering::Relaxed) {
+                    warn!("Brew is ongoing");
+                    let _ = resp.send(Err(AppError::BrewOngoing));
+                    continue;
+                }
 
-                let cloned_running = running.clone();
+                match recover(&db, cloned.clone(), &settings.safety, id, action).await {
+                    Ok(Some((steps, entry))) => {
+                        let _ = resp.send(Ok(()));
+                        (id, steps, entry)
+                    }
+                    Ok(None) => {
+                        let _ = resp.send(Ok(()));
+                        continue;
+                    }
+                    Err(err) => {
+                        let _ = resp.send(Err(err));
+                        continue;
+                    }
+                }
+            }
+            Command::Pause { id, resp } => {
+                let _ = resp.send(control(&current, &running, id, Control::Pause));
+                continue;
+            }
+            Command::Resume { id, resp } => {
+                let _ = resp.send(control(&current, &running, id, Control::Run));
+                continue;
+            }
+            Command::Abort { id, resp } => {
+                let _ = resp.send(control(&current, &running, id, Control::Abort));
+                continue;
+            }
+            Command::Confirm { id, resp } => {
+                let waiting = progress_rx.borrow().as_ref().is_some_and(|progress| {
+                    progress.id == id && progress.phase == models::Phase::Waiting
+                });
 
-                tokio::spawn(async move {
-                    if let Err(err) = run_program(id, cloned, steps, db).await {
-                        error!("{}", err);
+                let result = match &current {
+                    Some(current) if current.id == id && waiting => {
+                        current.confirmation.notify_one();
+                        Ok(())
                     }
+                    _ => {
+                        warn!("Brew {} is not waiting for a confirmation", id);
+                        Err(AppError::NotAwaitingConfirmation(id))
+                    }
+                };
 
-                    cloned_running.store(false, Ordering::Relaxed);
-                });
+                let _ = resp.send(result);
+                continue;
+            }
+            Command::Status { resp } => {
+                let paused = current
+                    .as_ref()
+                    .is_some_and(|current| *current.control.borrow() == Control::Pause);
 
-                let _ = resp.send(Ok(()));
+                let status = progress_rx
+                    .borrow()
+                    .as_ref()
+                    .map(|progress| progress.status(paused, clock.elapsed()));
+
+                let _ = resp.send(Ok(status));
+                continue;
             }
-        }
+        };
+
+        running.store(true, Ordering::Relaxed);
+
+        let db = db.clone();
+        let (control_tx, control_rx) = watch::channel(Control::Run);
+        let confirmation = Arc::new(Notify::new());
+
+        current = Some(Current {
+            id,
+            control: control_tx,
+            confirmation: confirmation.clone(),
+        });
+
+        progress_tx.send_replace(Some(Progress::new(id, &steps)));
+
+        let cloned_running = running.clone();
+        let progress = progress_tx.clone();
+
+        let mut program = Program {
+            id,
+            tx: cloned.clone(),
+            db,
+            control: control_rx,
+            progress: progress.clone(),
+            events: events.clone(),
+            clock: clock.clone(),
+            safety: Supervisor::new(settings.safety.clone()),
+            boil: settings.boil.clone(),
+            tolerance: settings.tolerance.clone(),
+            confirmation,
+        };
+
+        tokio::spawn(async move {
+            let result = program.run(steps, entry).await;
+            let safe_temperature = program.safety.safe_temperature();
+
+            progress.send_replace(None);
+
+            let (state, message) = match result {
+                Err(AppError::BrewAborted) => {
+                    info!("Brew aborted");
+                    (models::BrewState::Aborted, None)
+                }
+                Err(AppError::SafetyViolation(reason)) => {
+                    error!("{}", reason);
+
+                    program.emit(models::Event::Alarm {
+                        id,
+                        reason: reason.clone(),
+                    });
+
+                    let err = AppError::SafetyViolation(reason);
+                    (models::BrewState::Failed, Some(err.to_string()))
+                }
+                Err(err) => {
+                    error!("{}", err);
+                    (models::BrewState::Failed, Some(err.to_string()))
+                }
+                Ok(()) => (models::BrewState::Completed, None),
+            };
+
+            // Whatever ended the brew, the heater must not keep the last target, e.g. boiling.
+            info!("Brew ended, setting {}C", safe_temperature);
+
+            if let Err(err) = set_temperature(cloned, safe_temperature).await {
+                error!("Could not set safe temperature: {}", err);
+            }
+
+            match set_stirrer(program.tx.clone(), false).await {
+                Ok(()) | Err(AppError::Unsupported(_)) => {}
+                Err(err) => error!("Could not turn off stirrer: {}", err),
+            }
+
+            if let Err(err) = program.db.finish_brew(id, state, message).await {
+                error!("Could not record end of brew: {}", err);
+            }
+
+            cloned_running.store(false, Ordering::Relaxed);
+        });
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::db::Database;
+    use std::sync::Mutex;
+
+    /// Device command as recorded by the fake device.
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Read,
+        SetTemperature(f32),
+        SetStirrer(bool),
+    }
+
+    /// Fake device whose kettle jumps to the target temperature.
+    #[derive(Default)]
+    struct Fake {
+        calls: Mutex<Vec<Call>>,
+        state: Mutex<models::Device>,
+    }
+
+    impl Fake {
+        /// Spawn a task answering device commands and recording them.
+        fn spawn() -> (devices::Sender, Arc<Self>) {
+            let (tx, mut rx) = mpsc::channel(32);
+            let fake = Arc::new(Fake::default());
+            let cloned = fake.clone();
+
+            *fake.state.lock().unwrap() = models::Device {
+                current_temperature: Some(20.0),
+                target_temperature: Some(20.0),
+                ..Default::default()
+            };
+
+            tokio::spawn(async move {
+                while let Some(command) = rx.recv().await {
+                    cloned.handle(command);
+                }
+            });
+
+            (tx, fake)
+        }
+
+        fn handle(&self, command: devices::Command) {
+            let mut calls = self.calls.lock().unwrap();
+            let mut state = self.state.lock().unwrap();
+
+            match command {
+                devices::Command::Read { resp } => {
+                    calls.push(Call::Read);
+                    let _ = resp.send(Ok(state.clone()));
+                }
+                devices::Command::SetTemperature { temperature, resp } => {
+                    calls.push(Call::SetTemperature(temperature));
+                    state.current_temperature = Some(temperature);
+                    state.target_temperature = Some(temperature);
+                    let _ = resp.send(Ok(()));
+                }
+                devices::Command::SetStirrer { on, resp } => {
+                    calls.push(Call::SetStirrer(on));
+                    state.stirrer_on = on;
+                    let _ = resp.send(Ok(()));
+                }
+                devices::Command::Health { resp } => {
+                    let _ = resp.send(Ok(models::DeviceHealth::default()));
+                }
+                devices::Command::Info { resp } => {
+                    let _ = resp.send(Ok(models::DeviceInfo::default()));
+                }
+            }
+        }
+    }
+
+    /// Spawn the brew handler on a manual clock talking to a fake device.
+    async fn spawn_handler() -> Result<(Sender, Database, Clock, Arc<Fake>)> {
+        let clock = Clock::manual(Duration::ZERO);
+        let db = Database::new(None, clock.clone()).await?;
+        let (device_tx, fake) = Fake::spawn();
+        let (tx, rx) = mpsc::channel(32);
+
+        let settings = Settings {
+            safety: config::Safety {
+                // The fake kettle jumps to its target.
+                max_rate_of_rise: 1000.0,
+                ..Default::default()
+            },
+            boil: config::Boil::default(),
+            tolerance: config::Tolerance::default(),
+        };
+
+        tokio::spawn(run(
+            device_tx,
+            rx,
+            db.clone(),
+            events::channel(),
+            clock.clone(),
+            settings,
+        ));
+
+        Ok((tx, db, clock, fake))
+    }
+
+    /// Store a recipe with `steps`.
+    async fn add_recipe(db: &Database, steps: Vec<models::Step>) -> Result<models::Recipe> {
+        let recipe = models::NewRecipe {
+            name: "Pils".to_string(),
+            description: String::new(),
+            steps,
+        };
+
+        db.recipe(db.add_recipe(recipe).await?.id).await
+    }
+
+    async fn start(tx: &Sender, recipe: models::Recipe) -> Result<models::BrewId> {
+        let (resp, rx) = oneshot::channel();
+        let _ = tx.send(Command::Start { recipe, resp }).await;
+        rx.await?
+    }
+
+    fn hold(target_temperature: f32, duration: Duration) -> models::Step {
+        models::Step::Hold(models::HoldStep {
+            target_temperature,
+            duration,
+            stirrer_on: true,
+            additions: vec![],
+            tolerance: models::Tolerance::default(),
+        })
+    }
+
+    /// Keep advancing the manual `clock` whenever the program sleeps on it.
+    fn drive(clock: &Clock) {
+        let clock = clock.clone();
+
+        tokio::spawn(async move {
+            loop {
+                clock.advance().await;
+            }
+        });
+    }
+
+    /// Wait until brew `id` is no longer running.
+    async fn wait_until_finished(db: &Database, id: models::BrewId) -> Result<()> {
+        while db.brew(id).await?.state == models::BrewState::Running {
+            tokio::task::yield_now().await;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn runs_hold_and_boil() -> Result<()> {
+        let (tx, db, clock, fake) = spawn_handler().await?;
+        drive(&clock);
+
+        let boil = models::Step::Boil(models::BoilStep {
+            duration: Duration::from_secs(10),
+            stirrer_on: false,
+            additions: vec![],
+            tolerance: models::Tolerance::default(),
+        });
+
+        let recipe = add_recipe(&db, vec![hold(65.0, Duration::from_secs(10)), boil]).await?;
+        let id = start(&tx, recipe.clone()).await?;
+
+        // A second brew is refused without leaving a record behind.
+        assert!(matches!(
+            start(&tx, recipe).await,
+            Err(AppError::BrewOngoing)
+        ));
+        assert_eq!(db.brews().await?.brews.len(), 1);
+
+        wait_until_finished(&db, id).await?;
+
+        assert_eq!(db.brew(id).await?.state, models::BrewState::Completed);
+
+        assert_eq!(
+            *fake.calls.lock().unwrap(),
+            [
+                Call::SetStirrer(true),
+                Call::SetTemperature(65.0),
+                // Reaching the target and holding it for two poll intervals.
+                Call::Read,
+                Call::Read,
+                Call::Read,
+                Call::SetStirrer(false),
+                Call::SetTemperature(101.0),
+                Call::Read,
+                Call::Read,
+                Call::Read,
+                // The safe temperature after boiling and at the end of the brew.
+                Call::SetTemperature(20.0),
+                Call::SetTemperature(20.0),
+                Call::SetStirrer(false),
+            ]
+        );
+
+        let measurements = db.measurements(id).await?;
+
+        let temperatures = measurements
+            .iter()
+            .map(|measurement| measurement.temperature)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            temperatures,
+            [65.0, 65.0, 65.0, 101.0, 101.0, 101.0].map(Some)
+        );
+
+        let timestamps = measurements
+            .iter()
+            .map(|measurement| measurement.timestamp)
+            .collect::<Vec<_>>();
+
+        assert_eq!(timestamps, [0, 5, 10, 10, 15, 20]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overheating_while_paused_fails() -> Result<()> {
+        let (tx, db, clock, fake) = spawn_handler().await?;
+
+        let recipe = add_recipe(&db, vec![hold(65.0, Duration::from_secs(10))]).await?;
+        let id = start(&tx, recipe).await?;
+
+        let (resp, paused) = oneshot::channel();
+        let _ = tx.send(Command::Pause { id, resp }).await;
+        paused.await??;
+
+        drive(&clock);
+
+        // Still sampled well beyond the hold, which only ends after two more readings when running.
+        let sampled = async {
+            while db.measurements(id).await?.len() < 5 {
+                tokio::task::yield_now().await;
+            }
+
+            Ok::<_, AppError>(())
+        };
+
+        tokio::time::timeout(Duration::from_secs(10), sampled)
+            .await
+            .expect("not sampled while paused")?;
+
+        assert_eq!(db.brew(id).await?.state, models::BrewState::Running);
+
+        fake.state.lock().unwrap().current_temperature = Some(110.0);
+        tokio::time::timeout(Duration::from_secs(10), wait_until_finished(&db, id))
+            .await
+            .expect("not failed while paused")?;
+
+        let brew = db.brew(id).await?;
+        assert_eq!(brew.state, models::BrewState::Failed);
+        assert!(brew.error.unwrap().contains("exceeds"));
+
+        let calls = fake.calls.lock().unwrap();
+        assert!(calls.ends_with(&[
+            Call::Read,
+            Call::SetTemperature(20.0),
+            Call::SetStirrer(false)
+        ]));
+
+        Ok(())
+    }
+}
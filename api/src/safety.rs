@@ -0,0 +1,112 @@
+//! Supervision of device readings against the configured safety limits while a brew runs.
+
+use crate::config::Safety;
+use crate::{AppError, Result};
+use tokio::time::Duration;
+
+/// Shortest interval over which the rate of rise is computed, so that sensor noise between close
+/// readings does not trip the limit.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Decrease of the distance to the target temperature counted as heating progress.
+const MIN_PROGRESS: f32 = 0.5;
+
+/// Checks readings of a single brew against the safety limits.
+#[derive(Debug)]
+pub struct Supervisor {
+    limits: Safety,
+    missing: u32,
+    /// Clock time and temperature the rate of rise is computed against.
+    reference: Option<(Duration, f32)>,
+    /// Clock time and distance to the target of the last heating progress.
+    progress: Option<(Duration, f32)>,
+}
+
+impl Supervisor {
+    pub fn new(limits: Safety) -> Self {
+        Self {
+            limits,
+            missing: 0,
+            reference: None,
+            progress: None,
+        }
+    }
+
+    /// Target temperature to fall back to when the brew stops.
+    pub fn safe_temperature(&self) -> f32 {
+        self.limits.safe_temperature
+    }
+
+    /// Check `temperature` read at clock time `now`.
+    pub fn check(&mut self, now: Duration, temperature: Option<f32>) -> Result<()> {
+        let Some(temperature) = temperature else {
+            self.missing += 1;
+
+            if self.missing >= self.limits.max_missing_readings {
+                return Err(AppError::SafetyViolation(format!(
+                    "no temperature for {} consecutive readings",
+                    self.missing
+                )));
+            }
+
+            return Ok(());
+        };
+
+        self.missing = 0;
+
+        if temperature > self.limits.max_temperature {
+            return Err(AppError::SafetyViolation(format!(
+                "temperature {:.1}C exceeds {:.1}C",
+                temperature, self.limits.max_temperature
+            )));
+        }
+
+        match self.reference {
+            Some((since, reference)) if now - since >= RATE_WINDOW => {
+                let rate = (temperature - reference) / (now - since).as_secs_f32() * 60.0;
+                self.reference = Some((now, temperature));
+
+                if rate > self.limits.max_rate_of_rise {
+                    return Err(AppError::SafetyViolation(format!(
+                        "temperature rises by {:.1}C/min, more than {:.1}C/min",
+                        rate, self.limits.max_rate_of_rise
+                    )));
+                }
+            }
+            Some(_) => {}
+            None => self.reference = Some((now, temperature)),
+        }
+
+        Ok(())
+    }
+
+    /// Forget heating progress, e.g. when a new target is set or the brew was paused.
+    pub fn reset_progress(&mut self) {
+        self.progress = None;
+    }
+
+    /// Check that `temperature` read at clock time `now` keeps approaching `target`.
+    pub fn check_progress(&mut self, now: Duration, temperature: f32, target: f32) -> Result<()> {
+        let distance = (target - temperature).abs();
+
+        match self.progress {
+            Some((_, best)) if best - distance >= MIN_PROGRESS => {
+                self.progress = Some((now, distance));
+            }
+            Some((since, _)) => {
+                let timeout = Duration::from_secs(self.limits.no_progress_timeout);
+
+                if now - since > timeout {
+                    return Err(AppError::SafetyViolation(format!(
+                        "temperature did not approach {:.1}C within {} s",
+                        target,
+                        timeout.as_secs()
+                    )));
+                }
+            }
+            None => self.progress = Some((now, distance)),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+ALTER TABLE brews ADD COLUMN started_at INTEGER;
+ALTER TABLE brews ADD COLUMN finished_at INTEGER;
+ALTER TABLE brews ADD COLUMN status TEXT NOT NULL DEFAULT 'running';
+ALTER TABLE brews ADD COLUMN error TEXT;
+
+-- Brews recorded before lifecycle tracking are not running anymore.
+UPDATE brews SET status = 'completed';
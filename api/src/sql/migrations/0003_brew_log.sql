@@ -0,0 +1,11 @@
+ALTER TABLE brew_measurements ADD COLUMN stirring INTEGER;
+
+CREATE TABLE brew_steps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    brew_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    started_at INTEGER,
+    reached_at INTEGER,
+    finished_at INTEGER,
+    FOREIGN KEY(brew_id) REFERENCES brews(id)
+);
@@ -0,0 +1 @@
+ALTER TABLE steps ADD COLUMN stirrer INTEGER NOT NULL DEFAULT 0;
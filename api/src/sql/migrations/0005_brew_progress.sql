@@ -0,0 +1,3 @@
+ALTER TABLE brews ADD COLUMN progress_step INTEGER;
+ALTER TABLE brews ADD COLUMN progress_phase TEXT;
+ALTER TABLE brews ADD COLUMN progress_held INTEGER;
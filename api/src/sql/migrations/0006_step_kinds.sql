@@ -0,0 +1,3 @@
+ALTER TABLE steps ADD COLUMN kind TEXT NOT NULL DEFAULT 'hold';
+ALTER TABLE steps ADD COLUMN rate REAL;
+ALTER TABLE steps ADD COLUMN message TEXT;
@@ -0,0 +1,20 @@
+CREATE TABLE additions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    recipe_id INTEGER NOT NULL,
+    step INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    ingredient TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    before_end INTEGER NOT NULL,
+    FOREIGN KEY(recipe_id) REFERENCES recipes(id)
+);
+
+CREATE TABLE brew_additions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    brew_id INTEGER NOT NULL,
+    step INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    alerted_at INTEGER,
+    acknowledged_at INTEGER,
+    FOREIGN KEY(brew_id) REFERENCES brews(id)
+);
@@ -0,0 +1,13 @@
+ALTER TABLE steps ADD COLUMN tolerance_band REAL;
+ALTER TABLE steps ADD COLUMN settle_samples INTEGER;
+ALTER TABLE steps ADD COLUMN out_of_band TEXT;
+
+CREATE TABLE brew_deviations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    brew_id INTEGER NOT NULL,
+    step INTEGER NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER,
+    max_deviation REAL NOT NULL,
+    FOREIGN KEY(brew_id) REFERENCES brews(id)
+);
@@ -0,0 +1 @@
+ALTER TABLE brews ADD COLUMN recipe TEXT;
@@ -0,0 +1,134 @@
+//! Validation of user supplied data before it is stored or executed.
+
+use crate::config::Limits;
+use crate::{AppError, Result};
+
+fn error(field: impl Into<String>, message: impl Into<String>) -> models::FieldError {
+    models::FieldError {
+        field: field.into(),
+        message: message.into(),
+    }
+}
+
+fn check_temperature(
+    field: String,
+    temperature: f32,
+    limits: &Limits,
+) -> Option<models::FieldError> {
+    if (limits.min_temperature..=limits.max_temperature).contains(&temperature) {
+        None
+    } else {
+        Some(error(
+            field,
+            format!(
+                "must be between {} and {} °C",
+                limits.min_temperature, limits.max_temperature
+            ),
+        ))
+    }
+}
+
+/// Validate a manually set target `temperature` against `limits`.
+pub fn target_temperature(temperature: f32, limits: &Limits) -> Result<()> {
+    match check_temperature("target_temperature".to_string(), temperature, limits) {
+        None => Ok(()),
+        Some(error) => Err(AppError::Validation(models::ValidationErrors {
+            errors: vec![error],
+        })),
+    }
+}
+
+/// Validate `recipe` against `limits` and return all violations at once.
+pub fn recipe(recipe: &models::NewRecipe, limits: &Limits) -> Result<()> {
+    let mut errors = vec![];
+
+    if recipe.name.trim().is_empty() {
+        errors.push(error("name", "must not be empty"));
+    }
+
+    if recipe.steps.len() > limits.max_steps {
+        errors.push(error(
+            "steps",
+            format!("must not have more than {} steps", limits.max_steps),
+        ));
+    }
+
+    for (index, step) in recipe.steps.iter().enumerate() {
+        if let Some(temperature) = step.target_temperature() {
+            if let Some(error) = check_temperature(
+                format!("steps[{index}].target_temperature"),
+                temperature,
+                limits,
+            ) {
+                errors.push(error);
+            }
+        }
+
+        match step {
+            models::Step::Hold(models::HoldStep { duration, .. })
+            | models::Step::Boil(models::BoilStep { duration, .. })
+            | models::Step::Reminder(models::ReminderStep { duration, .. })
+                if duration.is_zero() =>
+            {
+                errors.push(error(
+                    format!("steps[{index}].duration"),
+                    "must not be zero",
+                ));
+            }
+            models::Step::Ramp(models::RampStep { rate, .. }) if *rate <= 0.0 => {
+                errors.push(error(format!("steps[{index}].rate"), "must be positive"));
+            }
+            _ => {}
+        }
+
+        if let Some(tolerance) = step.tolerance() {
+            if tolerance.band.is_some_and(|band| band <= 0.0) {
+                errors.push(error(
+                    format!("steps[{index}].tolerance.band"),
+                    "must be positive",
+                ));
+            }
+
+            if tolerance.settle_samples == Some(0) {
+                errors.push(error(
+                    format!("steps[{index}].tolerance.settle_samples"),
+                    "must not be zero",
+                ));
+            }
+        }
+
+        for (position, addition) in step.additions().iter().enumerate() {
+            let field = format!("steps[{index}].additions[{position}]");
+
+            if addition.ingredient.trim().is_empty() {
+                errors.push(error(format!("{field}.ingredient"), "must not be empty"));
+            }
+
+            if addition.before_end > step.duration() {
+                errors.push(error(
+                    format!("{field}.before_end"),
+                    "must not exceed the step duration",
+                ));
+            }
+        }
+
+        match step {
+            models::Step::Confirm(models::ConfirmStep { message, .. })
+            | models::Step::Reminder(models::ReminderStep { message, .. })
+                if message.trim().is_empty() =>
+            {
+                errors.push(error(
+                    format!("steps[{index}].message"),
+                    "must not be empty",
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(models::ValidationErrors { errors }))
+    }
+}
@@ -0,0 +1,62 @@
+use gloo_net::http::Request;
+use yew::prelude::*;
+
+/// Addition announced by the server and not acknowledged yet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DueAddition {
+    pub id: models::BrewId,
+    pub step: usize,
+    pub addition: usize,
+    pub ingredient: String,
+    pub amount: String,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub additions: Vec<DueAddition>,
+    /// Called once the server recorded the acknowledgement.
+    pub on_acknowledged: Callback<DueAddition>,
+}
+
+/// Acknowledge `addition` and call `done` once it was recorded.
+fn acknowledge(addition: DueAddition, done: Callback<DueAddition>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let route = format!(
+            "/api/brews/{}/steps/{}/additions/{}/acknowledge",
+            addition.id, addition.step, addition.addition
+        );
+
+        match Request::post(&route).send().await {
+            Ok(resp) if resp.ok() => done.emit(addition),
+            Ok(resp) => log::error!("{route}: {}", resp.status_text()),
+            Err(err) => log::error!("{route}: {err}"),
+        }
+    });
+}
+
+/// Ingredients to add now, each with a button to acknowledge the addition.
+#[function_component(Additions)]
+pub fn additions(
+    Props {
+        additions,
+        on_acknowledged,
+    }: &Props,
+) -> Html {
+    additions
+        .iter()
+        .map(|addition| {
+            let onclick = {
+                let addition = addition.clone();
+                let done = on_acknowledged.clone();
+                Callback::from(move |_| acknowledge(addition.clone(), done.clone()))
+            };
+
+            html! {
+                <div class="addition center">
+                    { format!("Add {} of {} now", addition.amount, addition.ingredient) }
+                    <button onclick={onclick}>{"Added"}</button>
+                </div>
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,91 @@
+use crate::components::TextInput;
+use gloo_net::http::Request;
+use serde::Serialize;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub device: models::Device,
+    /// Manual control is disabled while a brew is running.
+    pub brewing: bool,
+}
+
+fn post<T: Serialize + 'static>(route: &'static str, payload: T) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let body = serde_json::to_string(&payload).unwrap();
+
+        let result = Request::post(route)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .unwrap()
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if resp.ok() => {}
+            Ok(resp) => log::error!("{route}: {}", resp.status_text()),
+            Err(err) => log::error!("{route}: {err}"),
+        }
+    });
+}
+
+#[function_component(Controls)]
+pub fn controls(Props { device, brewing }: &Props) -> Html {
+    let target = use_state(String::default);
+
+    let on_change = {
+        let target = target.clone();
+
+        Callback::from(move |value| {
+            target.set(value);
+        })
+    };
+
+    let on_set = {
+        let target = target.clone();
+
+        Callback::from(move |_| match target.parse::<f32>() {
+            Ok(target_temperature) => post(
+                "/api/target-temperature",
+                models::TargetTemperature {
+                    target_temperature,
+                    force: false,
+                },
+            ),
+            Err(err) => log::error!("invalid temperature: {err}"),
+        })
+    };
+
+    let stirrer_on = !device.stirrer_on;
+
+    let on_stirrer = Callback::from(move |_| {
+        post(
+            "/api/stirrer",
+            models::Stirrer {
+                stirrer_on,
+                force: false,
+            },
+        )
+    });
+
+    let on_heater_off =
+        Callback::from(move |_| post("/api/heater-off", models::HeaterOff { force: false }));
+
+    let stirrer_label = if device.stirrer_on {
+        "Stirrer off"
+    } else {
+        "Stirrer on"
+    };
+
+    #[allow(clippy::unnecessary_operation)]
+    let controls = html! {
+        <span class="controls">
+            <TextInput on_change={on_change}/>
+            <button onclick={on_set} disabled={*brewing}>{"Set °C"}</button>
+            <button onclick={on_heater_off} disabled={*brewing}>{"Heater off"}</button>
+            <button onclick={on_stirrer} disabled={*brewing}>{stirrer_label}</button>
+        </span>
+    };
+
+    controls
+}
@@ -1,18 +1,93 @@
-use crate::components::Temperature;
+use crate::components::{Controls, Temperature};
+use gloo_net::http::Request;
 use yew::prelude::*;
 
 #[derive(Properties, PartialEq)]
 pub struct Props {
     pub device: models::Device,
+    pub brew: Option<models::BrewStatus>,
+    pub alarm: Option<String>,
+    /// Brew and message of the step waiting for confirmation.
+    pub confirmation: Option<(models::BrewId, String)>,
+    /// Message of the last reminder.
+    pub reminder: Option<String>,
+}
+
+/// Confirm the step brew `id` is waiting at.
+fn confirm(id: models::BrewId) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let route = format!("/api/brews/{id}/confirm");
+        let result = Request::post(&route).send().await;
+
+        match result {
+            Ok(resp) if resp.ok() => {}
+            Ok(resp) => log::error!("{route}: {}", resp.status_text()),
+            Err(err) => log::error!("{route}: {err}"),
+        }
+    });
+}
+
+fn brew_status(status: &models::BrewStatus) -> Html {
+    let phase = match (status.paused, status.phase) {
+        (true, _) => "paused",
+        (false, models::Phase::Heating) => "heating",
+        (false, models::Phase::Holding) => "holding",
+        (false, models::Phase::Waiting) => "waiting",
+    };
+
+    let band = if status.out_of_band {
+        ", out of band"
+    } else {
+        ""
+    };
+
+    html! {
+        <span class="brew-status">
+            { format!("Step {} {}{band}, {} min left", status.step + 1, phase, status.remaining.as_secs() / 60) }
+        </span>
+    }
 }
 
 #[function_component(Header)]
-pub fn header(Props { device }: &Props) -> Html {
+pub fn header(
+    Props {
+        device,
+        brew,
+        alarm,
+        confirmation,
+        reminder,
+    }: &Props,
+) -> Html {
     html! {
         <header class="header">
             <div class="center">
                 <Temperature temperature={device.current_temperature} emphasize=true/>
                 <Temperature temperature={device.target_temperature} emphasize=false/>
+                { brew.as_ref().map(brew_status).unwrap_or_default() }
+                {
+                    alarm.as_ref().map(|reason| html! {
+                        <span class="alarm">{ format!("Brew stopped: {reason}") }</span>
+                    }).unwrap_or_default()
+                }
+                {
+                    confirmation.as_ref().map(|(id, message)| {
+                        let id = *id;
+                        let onclick = Callback::from(move |_| confirm(id));
+
+                        html! {
+                            <span class="confirmation">
+                                { message.clone() }
+                                <button onclick={onclick}>{"Done"}</button>
+                            </span>
+                        }
+                    }).unwrap_or_default()
+                }
+                {
+                    reminder.as_ref().map(|message| html! {
+                        <span class="reminder">{ format!("Reminder: {message}") }</span>
+                    }).unwrap_or_default()
+                }
+                <Controls device={device.clone()} brewing={brew.is_some()}/>
             </div>
         </header>
     }
@@ -0,0 +1,93 @@
+use gloo_net::http::Request;
+use yew::prelude::*;
+
+/// Send the recovery `action` for brew `id` and call `done` once it was accepted.
+fn recover(id: models::BrewId, action: models::RecoveryAction, done: Callback<models::BrewId>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let route = format!("/api/brews/{id}/recover");
+        let body = serde_json::to_string(&models::Recovery { action }).unwrap();
+
+        let result = Request::post(&route)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .unwrap()
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if resp.ok() => done.emit(id),
+            Ok(resp) => log::error!("{route}: {}", resp.status_text()),
+            Err(err) => log::error!("{route}: {err}"),
+        }
+    });
+}
+
+/// Brews interrupted by a server restart with the choice to resume, restart the step or abort.
+#[function_component(Interrupted)]
+pub fn interrupted() -> Html {
+    let brews = use_state(models::Brews::default);
+
+    {
+        let brews = brews.clone();
+
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    // TODO: proper error handling
+                    let fetched: models::Brews = Request::get("/api/brews/interrupted")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+
+                    brews.set(fetched);
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    let done = {
+        let brews = brews.clone();
+
+        Callback::from(move |id| {
+            let mut remaining = (*brews).clone();
+            remaining.brews.retain(|brew| brew.id != id);
+            brews.set(remaining);
+        })
+    };
+
+    brews
+        .brews
+        .iter()
+        .map(|brew| {
+            let id = brew.id;
+            let description = match brew.progress {
+                Some(progress) => format!(
+                    "Brew {id} was interrupted in step {} after holding {} min",
+                    progress.step + 1,
+                    progress.held.as_secs() / 60
+                ),
+                None => format!("Brew {id} was interrupted before the first step"),
+            };
+
+            let button = |label: &'static str, action| {
+                let done = done.clone();
+                let onclick = Callback::from(move |_| recover(id, action, done.clone()));
+                html! { <button onclick={onclick}>{ label }</button> }
+            };
+
+            html! {
+                <div class="interrupted">
+                    { description }
+                    { button("Resume", models::RecoveryAction::Resume) }
+                    { button("Restart step", models::RecoveryAction::RestartStep) }
+                    { button("Abort", models::RecoveryAction::Abort) }
+                </div>
+            }
+        })
+        .collect()
+}
@@ -1,10 +1,16 @@
+mod additions;
+mod controls;
 mod header;
+mod interrupted;
 mod recipe;
 mod recipes_list;
 mod temperature;
 mod text_input;
 
+pub use additions::{Additions, DueAddition};
+pub use controls::Controls;
 pub use header::Header;
+pub use interrupted::Interrupted;
 pub use recipe::Recipe;
 pub use recipes_list::RecipesList;
 pub use temperature::Temperature;
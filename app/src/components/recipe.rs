@@ -11,8 +11,43 @@ pub fn recipe(Props { recipe }: &Props) -> Html {
         .steps
         .iter()
         .map(|step| {
+            let minutes = step.duration().as_secs() / 60;
+
+            let description = match step {
+                models::Step::Hold(step) => {
+                    format!("Hold {} °C for {minutes} min", step.target_temperature)
+                }
+                models::Step::Ramp(step) => format!(
+                    "Ramp to {} °C at {} °C/min",
+                    step.target_temperature, step.rate
+                ),
+                models::Step::Boil(_) => format!("Boil for {minutes} min"),
+                models::Step::Confirm(step) => format!("Confirm: {}", step.message),
+                models::Step::Reminder(step) => {
+                    format!("Remind after {minutes} min: {}", step.message)
+                }
+            };
+
+            let additions = step
+                .additions()
+                .iter()
+                .map(|addition| {
+                    html! {
+                        <li>{ format!(
+                            "{} {} at {} min before end",
+                            addition.amount,
+                            addition.ingredient,
+                            addition.before_end.as_secs() / 60
+                        ) }</li>
+                    }
+                })
+                .collect::<Html>();
+
             html! {
-                <p>{step.target_temperature} {"°C"} {format!("{:?}", step.duration)}</p>
+                <>
+                <p>{ description }</p>
+                <ul>{ additions }</ul>
+                </>
             }
         })
         .collect::<Html>();
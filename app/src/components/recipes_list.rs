@@ -13,11 +13,14 @@ pub fn recipes_list(Props { recipes }: &Props) -> Html {
         .recipes
         .iter()
         .map(|recipe| {
-            html! {
+            #[allow(clippy::unnecessary_operation)]
+            let link = html! {
                 <>
                 <Link<Route> to={Route::Recipe { id: recipe.id }}>{recipe.name.clone()}</Link<Route>>
                 </>
-            }
+            };
+
+            link
         })
         .collect()
 }
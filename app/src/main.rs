@@ -4,9 +4,11 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 mod components;
 mod pages;
 
-use anyhow::Result;
-use components::Header;
+use anyhow::{anyhow, Result};
+use components::{Additions, DueAddition, Header};
+use futures::StreamExt;
 use gloo::timers::callback::Interval;
+use gloo_net::eventsource::futures::EventSource;
 use gloo_net::http;
 use log::error;
 use std::sync::{Arc, RwLock};
@@ -31,15 +33,43 @@ enum Route {
 
 enum Message {
     Tick,
+    Event(models::Event),
+    Acknowledged(DueAddition),
 }
 
 struct Model {
-    device: Arc<RwLock<models::Device>>,
+    device: models::Device,
+    brew: Arc<RwLock<Option<models::BrewStatus>>>,
+    /// Reason of the last safety alarm, cleared when a step starts.
+    alarm: Option<String>,
+    /// Brew and message of the step waiting for confirmation, cleared when a step starts.
+    confirmation: Option<(models::BrewId, String)>,
+    /// Message of the last reminder, cleared when the brew finishes.
+    reminder: Option<String>,
+    /// Additions announced and not acknowledged yet.
+    additions: Vec<DueAddition>,
     _interval: Interval,
 }
 
-async fn fetch_state() -> Result<models::Device> {
-    Ok(http::Request::get("http://0.0.0.0:3000/api/state")
+/// Subscribe to the server event stream and forward events to `callback`.
+async fn subscribe_events(callback: Callback<models::Event>) -> Result<()> {
+    let mut source =
+        EventSource::new("http://0.0.0.0:3000/api/events").map_err(|err| anyhow!("{err}"))?;
+    let mut messages = source.subscribe("message")?;
+
+    while let Some(message) = messages.next().await {
+        let (_, message) = message.map_err(|err| anyhow!("{err:?}"))?;
+
+        if let Some(data) = message.data().as_string() {
+            callback.emit(serde_json::from_str(&data)?);
+        }
+    }
+
+    Ok(())
+}
+
+async fn fetch_brew_status() -> Result<Option<models::BrewStatus>> {
+    Ok(http::Request::get("http://0.0.0.0:3000/api/brews/current")
         .send()
         .await?
         .json()
@@ -52,10 +82,22 @@ impl Component for Model {
 
     fn create(ctx: &Context<Self>) -> Self {
         let link = ctx.link().clone();
+        let callback = ctx.link().callback(Message::Event);
         let interval = Interval::new(1000, move || link.send_message(Message::Tick));
 
+        spawn_local(async move {
+            if let Err(err) = subscribe_events(callback).await {
+                error!("error: {err}");
+            }
+        });
+
         Self {
-            device: Arc::new(RwLock::new(models::Device::default())),
+            device: models::Device::default(),
+            brew: Arc::new(RwLock::new(None)),
+            alarm: None,
+            confirmation: None,
+            reminder: None,
+            additions: vec![],
             _interval: interval,
         }
     }
@@ -63,15 +105,15 @@ impl Component for Model {
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Message::Tick => {
-                let device = self.device.clone();
+                let brew = self.brew.clone();
 
                 spawn_local(async move {
-                    match fetch_state().await {
-                        Ok(new_state) => {
+                    match fetch_brew_status().await {
+                        Ok(new_status) => {
                             // TODO: we use std::sync::RwLock here which should lock everything but
                             // it does not ... strange
-                            let mut device = device.write().unwrap();
-                            *device = new_state;
+                            let mut brew = brew.write().unwrap();
+                            *brew = new_status;
                         }
                         Err(err) => {
                             error!("error: {err}");
@@ -83,6 +125,52 @@ impl Component for Model {
                 // view to update ...
                 true
             }
+            Message::Event(models::Event::Device(device)) => {
+                self.device = device;
+                true
+            }
+            Message::Event(models::Event::Alarm { reason, .. }) => {
+                self.alarm = Some(reason);
+                true
+            }
+            Message::Event(models::Event::StepStarted { .. }) => {
+                self.alarm = None;
+                self.confirmation = None;
+                true
+            }
+            Message::Event(models::Event::ConfirmationRequired { id, message, .. }) => {
+                self.confirmation = Some((id, message));
+                true
+            }
+            Message::Event(models::Event::Reminder { message, .. }) => {
+                self.reminder = Some(message);
+                true
+            }
+            Message::Event(models::Event::AdditionDue {
+                id,
+                step,
+                addition,
+                ingredient,
+                amount,
+            }) => {
+                self.additions.push(DueAddition {
+                    id,
+                    step,
+                    addition,
+                    ingredient,
+                    amount,
+                });
+                true
+            }
+            Message::Event(models::Event::BrewFinished { .. }) => {
+                self.reminder = None;
+                true
+            }
+            Message::Acknowledged(addition) => {
+                self.additions.retain(|due| *due != addition);
+                true
+            }
+            Message::Event(_) => false,
         }
     }
 
@@ -90,12 +178,20 @@ impl Component for Model {
         false
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
-        let device = self.device.clone().read().unwrap().clone();
+    #[allow(clippy::unnecessary_operation)]
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let device = self.device.clone();
+        let brew = self.brew.clone().read().unwrap().clone();
+        let alarm = self.alarm.clone();
+        let confirmation = self.confirmation.clone();
+        let reminder = self.reminder.clone();
+        let additions = self.additions.clone();
+        let on_acknowledged = ctx.link().callback(Message::Acknowledged);
 
         html! {
             <div>
-                <Header device={device}/>
+                <Header device={device} brew={brew} alarm={alarm} confirmation={confirmation} reminder={reminder}/>
+                <Additions additions={additions} on_acknowledged={on_acknowledged}/>
                 <main class="center">
                     <BrowserRouter>
                         <Switch<Route> render={Switch::render(switch)} />
@@ -106,6 +202,7 @@ impl Component for Model {
     }
 }
 
+#[allow(clippy::let_unit_value)]
 fn switch(routes: &Route) -> Html {
     match routes {
         Route::Home => html! { <pages::Home/> },
@@ -1,3 +1,4 @@
+use crate::components::Interrupted;
 use crate::Route;
 use yew::prelude::*;
 use yew_router::prelude::*;
@@ -12,9 +13,13 @@ impl Component for Home {
         Self
     }
 
+    #[allow(clippy::let_unit_value, clippy::unnecessary_operation)]
     fn view(&self, _ctx: &Context<Self>) -> Html {
         html! {
+            <>
+            <Interrupted/>
             <Link<Route> to={Route::Recipes}>{ "Recipes" }</Link<Route>>
+            </>
         }
     }
 }
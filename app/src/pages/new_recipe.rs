@@ -1,21 +1,95 @@
 use crate::components::TextInput;
+use std::time::Duration;
 use yew::prelude::*;
 
-#[derive(Clone, Default)]
-struct Mash {
-    duration: u32,
-    temperature: u32,
+/// Step added by the button of the given kind.
+fn default_step(kind: &str) -> models::Step {
+    let duration = Duration::from_secs(10 * 60);
+
+    match kind {
+        "ramp" => models::Step::Ramp(models::RampStep {
+            target_temperature: 30.0,
+            rate: 1.0,
+            stirrer_on: false,
+        }),
+        "boil" => models::Step::Boil(models::BoilStep {
+            duration,
+            stirrer_on: false,
+            additions: vec![],
+            tolerance: models::Tolerance::default(),
+        }),
+        "confirm" => models::Step::Confirm(models::ConfirmStep {
+            message: String::default(),
+            stirrer_on: false,
+        }),
+        "reminder" => models::Step::Reminder(models::ReminderStep {
+            message: String::default(),
+            duration,
+            stirrer_on: false,
+        }),
+        _ => models::Step::Hold(models::HoldStep {
+            target_temperature: 30.0,
+            duration,
+            stirrer_on: false,
+            additions: vec![],
+            tolerance: models::Tolerance::default(),
+        }),
+    }
 }
 
-#[derive(Clone)]
-enum Step {
-    Mash(Mash),
+fn render_step(step: &models::Step) -> Html {
+    let minutes = format!("{}", step.duration().as_secs() / 60);
+
+    match step {
+        models::Step::Hold(hold) => {
+            let temperature = format!("{}", hold.target_temperature);
+
+            html! {
+                <p>
+                {"Hold "}
+                <input type="number" min="20" max="100" value={temperature}/>
+                <input type="number" min="1" max="60" value={minutes}/>
+                </p>
+            }
+        }
+        models::Step::Ramp(ramp) => {
+            let temperature = format!("{}", ramp.target_temperature);
+            let rate = format!("{}", ramp.rate);
+
+            html! {
+                <p>
+                {"Ramp "}
+                <input type="number" min="20" max="100" value={temperature}/>
+                <input type="number" min="0.1" max="10" step="0.1" value={rate}/>
+                </p>
+            }
+        }
+        models::Step::Boil(_) => html! {
+            <p>
+            {"Boil "}
+            <input type="number" min="1" max="180" value={minutes}/>
+            </p>
+        },
+        models::Step::Confirm(confirm) => html! {
+            <p>
+            {"Confirm "}
+            <input type="text" value={confirm.message.clone()}/>
+            </p>
+        },
+        models::Step::Reminder(reminder) => html! {
+            <p>
+            {"Reminder "}
+            <input type="text" value={reminder.message.clone()}/>
+            <input type="number" min="1" max="180" value={minutes}/>
+            </p>
+        },
+    }
 }
 
 #[function_component(NewRecipe)]
 pub fn new_recipe() -> Html {
     let name = use_state(String::default);
-    let steps = use_state(Vec::<Step>::default);
+    let steps = use_state(Vec::<models::Step>::default);
 
     let cloned_name = name.clone();
 
@@ -27,40 +101,33 @@ pub fn new_recipe() -> Html {
         log::info!("Storing {}", *cloned_name);
     });
 
-    let cloned_steps = steps.clone();
+    let new_step_button = |kind: &'static str| {
+        let steps = steps.clone();
 
-    let on_new_step = Callback::from(move |_| {
-        let mut new_steps = (*cloned_steps).clone();
-        new_steps.push(Step::Mash(Mash {
-            duration: 10,
-            temperature: 30,
-        }));
-        cloned_steps.set(new_steps);
-    });
+        let onclick = Callback::from(move |_| {
+            let mut new_steps = (*steps).clone();
+            new_steps.push(default_step(kind));
+            steps.set(new_steps);
+        });
 
-    let rendered_steps = steps
-        .iter()
-        .map(|step| match step {
-            Step::Mash(mash) => {
-                let temperature = format!("{}", mash.temperature);
-                let duration = format!("{}", mash.duration);
-
-                html! {
-                    <p>
-                    <input type="number" min="20" max="100" value={temperature}/>
-                    <input type="number" min="1" max="60" value={duration}/>
-                    </p>
-                }
-            }
-        })
-        .collect::<Html>();
+        html! { <button onclick={onclick}>{ format!("+ {kind}") }</button> }
+    };
 
-    html! {
+    let rendered_steps = steps.iter().map(render_step).collect::<Html>();
+
+    #[allow(clippy::unnecessary_operation)]
+    let form = html! {
         <>
         <TextInput on_change={on_change}/>
         {rendered_steps}
-        <button onclick={on_new_step}>{"+"}</button>
+        { new_step_button("hold") }
+        { new_step_button("ramp") }
+        { new_step_button("boil") }
+        { new_step_button("confirm") }
+        { new_step_button("reminder") }
         <button onclick={on_save}>{"Save"}</button>
         </>
-    }
+    };
+
+    form
 }
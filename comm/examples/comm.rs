@@ -6,21 +6,48 @@ use std::path::PathBuf;
 fn parse_temperature(src: &str) -> Result<f32> {
     let temperature = src.parse::<f32>()?;
 
-    if temperature < 20.0 || temperature > 99.0 {
+    if !(20.0..=99.0).contains(&temperature) {
         Err(anyhow!("Temperature must be between [20, 99]"))
     } else {
         Ok(temperature)
     }
 }
 
+fn parse_duty(src: &str) -> Result<f32> {
+    let duty = src.parse::<f32>()?;
+
+    if !(0.0..=1.0).contains(&duty) {
+        Err(anyhow!("Duty must be between [0, 1]"))
+    } else {
+        Ok(duty)
+    }
+}
+
 #[derive(Parser)]
-enum Opt {
+struct Opt {
+    /// Serial device of the Brewslave
+    #[arg(long, default_value = "/dev/ttyACM0")]
+    device: PathBuf,
+    /// Use the checksummed framed protocol
+    #[arg(long)]
+    framed: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
     StressTest {},
     Read {},
+    Info {},
     SetTemperature {
-        #[structopt(long, parse(try_from_str = parse_temperature))]
+        #[arg(long, value_parser = parse_temperature)]
         target: f32,
     },
+    SetHeaterDuty {
+        #[arg(long, value_parser = parse_duty)]
+        duty: f32,
+    },
 }
 
 async fn stress_test(client: comm::Comm) -> Result<()> {
@@ -32,7 +59,7 @@ async fn stress_test(client: comm::Comm) -> Result<()> {
 
     bar.set_style(
         indicatif::ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {msg}")
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {msg}")?
             .progress_chars("#:."),
     );
 
@@ -81,18 +108,31 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     let opts = Opt::parse();
-    let client = comm::Comm::new(&PathBuf::from("/dev/ttyACM0"))?;
 
-    match opts {
-        Opt::StressTest {} => {
+    let protocol = if opts.framed {
+        comm::Protocol::Framed
+    } else {
+        comm::Protocol::Legacy
+    };
+
+    let client = comm::Comm::with_protocol(&opts.device, protocol).await?;
+
+    match opts.command {
+        Command::StressTest {} => {
             stress_test(client).await?;
         }
-        Opt::Read {} => {
+        Command::Read {} => {
             println!("{:#?}", client.read_state().await?);
         }
-        Opt::SetTemperature { target } => {
+        Command::Info {} => {
+            println!("{:#?}", client.info());
+        }
+        Command::SetTemperature { target } => {
             client.set_temperature(target).await?;
         }
+        Command::SetHeaterDuty { duty } => {
+            client.set_heater_duty(duty).await?;
+        }
     };
 
     Ok(())
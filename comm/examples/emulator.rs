@@ -0,0 +1,73 @@
+use anyhow::Result;
+use clap::Parser;
+use comm::emulator::{Config, Emulator, Faults, Model};
+use std::time::Duration;
+
+#[derive(Parser)]
+struct Opt {
+    /// Use the checksummed framed protocol
+    #[arg(long)]
+    framed: bool,
+    /// Behave like firmware that does not answer the identify command
+    #[arg(long)]
+    no_identify: bool,
+    /// Probability that a reply byte is dropped
+    #[arg(long, default_value_t = 0.0, value_parser = probability)]
+    drop_byte: f64,
+    /// Probability that a request is answered with NACK
+    #[arg(long, default_value_t = 0.0, value_parser = probability)]
+    nack: f64,
+    /// Delay in milliseconds before each reply
+    #[arg(long, default_value_t = 0)]
+    delay: u64,
+    /// Report NaN instead of the current temperature
+    #[arg(long)]
+    nan_sensor: bool,
+    /// Factor by which emulated time runs faster than wall clock time
+    #[arg(long, default_value_t = 1.0)]
+    speedup: f32,
+}
+
+/// Parse a probability between zero and one.
+fn probability(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(probability) if (0.0..=1.0).contains(&probability) => Ok(probability),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let opts = Opt::parse();
+
+    let protocol = if opts.framed {
+        comm::Protocol::Framed
+    } else {
+        comm::Protocol::Legacy
+    };
+
+    let config = Config {
+        protocol,
+        identify: !opts.no_identify,
+        model: Model {
+            speedup: opts.speedup,
+            ..Model::default()
+        },
+        faults: Faults {
+            drop_byte: opts.drop_byte,
+            nack: opts.nack,
+            delay: Duration::from_millis(opts.delay),
+            nan_sensor: opts.nan_sensor,
+        },
+    };
+
+    let emulator = Emulator::spawn(config)?;
+    println!("Emulating Brewslave on {}", emulator.path().display());
+
+    loop {
+        std::thread::sleep(Duration::from_secs(5));
+        println!("{:?}", emulator.state());
+    }
+}
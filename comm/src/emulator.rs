@@ -0,0 +1,470 @@
+//! Brewslave emulator attached to a pseudo terminal.
+//!
+//! The emulator speaks the same bytes as the firmware, so [`Comm`](crate::Comm) can be pointed at
+//! [`Emulator::path`] in place of a real serial device. The kettle is modelled as a heater switched
+//! by a thermostat with hysteresis and heat loss proportional to the difference to ambient
+//! temperature. A heater duty set by the host replaces the thermostat and is modelled by its mean
+//! power. Once heartbeats were received, target temperature and duty are dropped and the heater
+//! turned off when they stop. [`Faults`] can be injected at any time to exercise error handling.
+
+use crate::{frame, Command, Protocol, State};
+use byteorder::{ByteOrder, LittleEndian};
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::{grantpt, posix_openpt, ptsname_r, unlockpt, PtyMaster};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+use rand::Rng;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const READ_STATE: u8 = Command::ReadState as u8;
+const SET_TEMPERATURE: u8 = Command::SetTemperature as u8;
+const TURN_STIRRER_ON: u8 = Command::TurnStirrerOn as u8;
+const TURN_STIRRER_OFF: u8 = Command::TurnStirrerOff as u8;
+const IDENTIFY: u8 = Command::Identify as u8;
+const HEARTBEAT: u8 = Command::Heartbeat as u8;
+const SET_HEATER_DUTY: u8 = Command::SetHeaterDuty as u8;
+
+/// Firmware version reported by the emulator.
+const FIRMWARE_VERSION: (u8, u8, u8) = (0, 1, 0);
+
+/// Protocol version reported by the emulator.
+const PROTOCOL_VERSION: u8 = 1;
+
+/// Boiling point the emulated temperature does not exceed.
+const BOILING_POINT: f32 = 100.0;
+
+/// Time to wait for input before the kettle model is advanced anyway.
+const POLL_TIMEOUT_MS: i32 = 50;
+
+/// Physical parameters of the emulated kettle.
+#[derive(Clone, Debug)]
+pub struct Model {
+    /// Initial and ambient temperature in degree Celsius.
+    pub ambient_temperature: f32,
+    /// Temperature rise in Kelvin per second while the heater is on.
+    pub heating_rate: f32,
+    /// Fraction of the difference to ambient temperature lost per second.
+    pub heat_loss: f32,
+    /// The heater switches on below target minus hysteresis and off at the target.
+    pub hysteresis: f32,
+    /// Factor by which emulated time runs faster than wall clock time.
+    pub speedup: f32,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        // Roughly 20 litres heated with 3 kW.
+        Self {
+            ambient_temperature: 20.0,
+            heating_rate: 0.036,
+            heat_loss: 0.0002,
+            hysteresis: 0.5,
+            speedup: 1.0,
+        }
+    }
+}
+
+/// Faults injected into the communication.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    /// Probability that a single reply byte is dropped. Values outside zero to one are clamped.
+    pub drop_byte: f64,
+    /// Probability that a request is answered with a NACK. Values outside zero to one are clamped.
+    pub nack: f64,
+    /// Delay before each reply is sent.
+    pub delay: Duration,
+    /// Report NaN instead of the current temperature.
+    pub nan_sensor: bool,
+}
+
+/// Emulator configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Wire format the emulator expects and answers in.
+    pub protocol: Protocol,
+    /// Answer the identify command, otherwise behave like firmware predating it.
+    pub identify: bool,
+    pub model: Model,
+    pub faults: Faults,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::Legacy,
+            identify: true,
+            model: Model::default(),
+            faults: Faults::default(),
+        }
+    }
+}
+
+/// Emulated kettle state.
+#[derive(Debug)]
+struct Kettle {
+    model: Model,
+    temperature: f32,
+    target_temperature: Option<f32>,
+    stirrer_on: bool,
+    heater_on: bool,
+    /// Heater duty between zero and one overriding the thermostat.
+    duty: Option<f32>,
+    /// Time of the last heartbeat and the requested timeout.
+    watchdog: Option<(Instant, Duration)>,
+}
+
+impl Kettle {
+    fn new(model: Model) -> Self {
+        Self {
+            temperature: model.ambient_temperature,
+            model,
+            target_temperature: None,
+            stirrer_on: false,
+            heater_on: false,
+            duty: None,
+            watchdog: None,
+        }
+    }
+
+    /// Advance the model by `elapsed` wall clock time up to `now`.
+    fn update(&mut self, now: Instant, elapsed: Duration) {
+        let dt = elapsed.as_secs_f32() * self.model.speedup;
+
+        if let Some((last, timeout)) = self.watchdog {
+            if now - last > timeout {
+                self.watchdog = None;
+                self.target_temperature = None;
+                self.duty = None;
+            }
+        }
+
+        if let Some(duty) = self.duty {
+            self.heater_on = duty > 0.0;
+            let heating = self.model.heating_rate * duty;
+            self.advance(heating, dt);
+            return;
+        }
+
+        self.heater_on = match self.target_temperature {
+            Some(target) if self.temperature >= target => false,
+            Some(target) if self.temperature < target - self.model.hysteresis => true,
+            Some(_) => self.heater_on,
+            None => false,
+        };
+
+        let heating = if self.heater_on {
+            self.model.heating_rate
+        } else {
+            0.0
+        };
+
+        self.advance(heating, dt);
+    }
+
+    /// Raise the temperature by `heating` Kelvin per second minus heat loss for `dt` seconds.
+    fn advance(&mut self, heating: f32, dt: f32) {
+        let loss = self.model.heat_loss * (self.temperature - self.model.ambient_temperature);
+        self.temperature = (self.temperature + (heating - loss) * dt).min(BOILING_POINT);
+    }
+
+    fn state(&self) -> State {
+        State {
+            current_temperature: Some(self.temperature),
+            target_temperature: self.target_temperature,
+            stirrer_on: self.stirrer_on,
+            heater_on: self.heater_on,
+        }
+    }
+}
+
+/// Reply to a single request before faults are applied.
+enum Reply {
+    Ack(Vec<u8>),
+    Nack,
+}
+
+/// Request handling state living in the emulator thread.
+struct Handler {
+    master: PtyMaster,
+    protocol: Protocol,
+    identify: bool,
+    kettle: Arc<Mutex<Kettle>>,
+    faults: Arc<Mutex<Faults>>,
+    input: Vec<u8>,
+}
+
+impl Handler {
+    /// Execute `command` with `payload` and return the reply payload.
+    fn execute(&self, command: u8, payload: &[u8]) -> Reply {
+        let mut kettle = self.kettle.lock().unwrap();
+
+        match command {
+            READ_STATE => {
+                let faults = self.faults.lock().unwrap();
+                let state = kettle.state();
+                let mut data = vec![0; super::STATE_LENGTH];
+
+                let current = match state.current_temperature {
+                    Some(_) if faults.nan_sensor => f32::NAN,
+                    Some(temperature) => temperature,
+                    None => f32::NAN,
+                };
+
+                LittleEndian::write_f32(&mut data[0..4], current);
+                LittleEndian::write_f32(
+                    &mut data[4..8],
+                    state.target_temperature.unwrap_or(f32::NAN),
+                );
+
+                if state.stirrer_on {
+                    data[8] |= super::RESPONSE_STIRRER_BIT;
+                }
+
+                if state.heater_on {
+                    data[8] |= super::RESPONSE_HEATER_BIT;
+                }
+
+                Reply::Ack(data)
+            }
+            SET_TEMPERATURE if payload.len() == 4 => {
+                kettle.target_temperature = Some(LittleEndian::read_f32(payload));
+                kettle.duty = None;
+                Reply::Ack(vec![])
+            }
+            TURN_STIRRER_ON => {
+                kettle.stirrer_on = true;
+                Reply::Ack(vec![])
+            }
+            TURN_STIRRER_OFF => {
+                kettle.stirrer_on = false;
+                Reply::Ack(vec![])
+            }
+            HEARTBEAT if payload.len() == 2 => {
+                let timeout = Duration::from_secs(LittleEndian::read_u16(payload).into());
+                kettle.watchdog = Some((Instant::now(), timeout));
+                Reply::Ack(vec![])
+            }
+            SET_HEATER_DUTY if payload.len() == 1 => {
+                kettle.duty = Some(f32::from(payload[0]) / 255.0);
+                Reply::Ack(vec![])
+            }
+            IDENTIFY if self.identify => {
+                let (major, minor, patch) = FIRMWARE_VERSION;
+                let capabilities = super::CAPABILITY_STIRRER_BIT
+                    | super::CAPABILITY_HEATER_BIT
+                    | super::CAPABILITY_WATCHDOG_BIT
+                    | super::CAPABILITY_HEATER_DUTY_BIT;
+                Reply::Ack(vec![major, minor, patch, PROTOCOL_VERSION, capabilities, 1])
+            }
+            _ => Reply::Nack,
+        }
+    }
+
+    /// Take the next complete legacy request from the input and return command and payload.
+    fn next_legacy(&mut self) -> Option<(u8, Vec<u8>)> {
+        let command = *self.input.first()?;
+
+        let length = match command {
+            SET_TEMPERATURE => 5,
+            HEARTBEAT => 3,
+            SET_HEATER_DUTY => 2,
+            _ => 1,
+        };
+
+        if self.input.len() < length {
+            return None;
+        }
+
+        let request: Vec<u8> = self.input.drain(..length).collect();
+        Some((command, request[1..].to_vec()))
+    }
+
+    /// Take the next framed request from the input. Garbage before the start byte is skipped and
+    /// frames with a wrong checksum are answered with command zero, which is always refused.
+    fn next_framed(&mut self) -> Option<(u8, Vec<u8>)> {
+        let start = self.input.iter().position(|byte| *byte == frame::START);
+        self.input.drain(..start.unwrap_or(self.input.len()));
+
+        let length = *self.input.get(1)? as usize;
+
+        if length > frame::MAX_PAYLOAD {
+            self.input.remove(0);
+            return Some((0, vec![]));
+        }
+
+        if self.input.len() < length + 4 {
+            return None;
+        }
+
+        let request: Vec<u8> = self.input.drain(..length + 4).collect();
+
+        if frame::crc8(&request[1..length + 3]) != request[length + 3] {
+            return Some((0, vec![]));
+        }
+
+        Some((request[2], request[3..length + 3].to_vec()))
+    }
+
+    /// Encode `reply` in the wire format, apply faults and send it.
+    fn send(&mut self, reply: Reply) -> io::Result<()> {
+        let faults = self.faults.lock().unwrap().clone();
+        let mut rng = rand::thread_rng();
+
+        let reply = match reply {
+            Reply::Ack(_) if chance(&mut rng, faults.nack) => Reply::Nack,
+            reply => reply,
+        };
+
+        let data = match (self.protocol, reply) {
+            (Protocol::Legacy, Reply::Ack(payload)) if payload.is_empty() => {
+                vec![super::RESPONSE_ACK]
+            }
+            (Protocol::Legacy, Reply::Ack(payload)) => payload,
+            (Protocol::Legacy, Reply::Nack) => vec![super::RESPONSE_NACK],
+            (Protocol::Framed, Reply::Ack(payload)) => frame::encode(super::RESPONSE_ACK, &payload),
+            (Protocol::Framed, Reply::Nack) => frame::encode(super::RESPONSE_NACK, &[]),
+        };
+
+        let data: Vec<u8> = data
+            .into_iter()
+            .filter(|_| !chance(&mut rng, faults.drop_byte))
+            .collect();
+
+        thread::sleep(faults.delay);
+        self.master.write_all(&data)?;
+        self.master.flush()
+    }
+
+    /// Serve requests until `stop` is set.
+    fn run(mut self, stop: Arc<AtomicBool>) -> io::Result<()> {
+        let mut last = Instant::now();
+        let mut buffer = [0; 64];
+
+        while !stop.load(Ordering::Relaxed) {
+            let mut fds = [PollFd::new(self.master.as_raw_fd(), PollFlags::POLLIN)];
+            let ready = poll(&mut fds, POLL_TIMEOUT_MS)?;
+
+            let now = Instant::now();
+            self.kettle.lock().unwrap().update(now, now - last);
+            last = now;
+
+            if ready == 0 {
+                continue;
+            }
+
+            let read = self.master.read(&mut buffer)?;
+            self.input.extend_from_slice(&buffer[..read]);
+
+            loop {
+                let request = match self.protocol {
+                    Protocol::Legacy => self.next_legacy(),
+                    Protocol::Framed => self.next_framed(),
+                };
+
+                let Some((command, payload)) = request else {
+                    break;
+                };
+
+                let reply = self.execute(command, &payload);
+                self.send(reply)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Running emulator. The emulator thread is stopped when this is dropped.
+pub struct Emulator {
+    path: PathBuf,
+    kettle: Arc<Mutex<Kettle>>,
+    faults: Arc<Mutex<Faults>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+    /// Kept open so that the master side does not fail while no client is connected.
+    _slave: File,
+}
+
+impl Emulator {
+    /// Open a new pseudo terminal and serve requests on it in a background thread.
+    pub fn spawn(config: Config) -> io::Result<Self> {
+        let master = posix_openpt(OFlag::O_RDWR | OFlag::O_NOCTTY)?;
+        grantpt(&master)?;
+        unlockpt(&master)?;
+        let path = PathBuf::from(ptsname_r(&master)?);
+
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(OFlag::O_NOCTTY.bits())
+            .open(&path)?;
+
+        let mut termios = tcgetattr(slave.as_raw_fd())?;
+        cfmakeraw(&mut termios);
+        tcsetattr(slave.as_raw_fd(), SetArg::TCSANOW, &termios)?;
+
+        let kettle = Arc::new(Mutex::new(Kettle::new(config.model)));
+        let faults = Arc::new(Mutex::new(config.faults));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handler = Handler {
+            master,
+            protocol: config.protocol,
+            identify: config.identify,
+            kettle: kettle.clone(),
+            faults: faults.clone(),
+            input: vec![],
+        };
+
+        let cloned = stop.clone();
+        let thread = thread::spawn(move || handler.run(cloned));
+
+        Ok(Self {
+            path,
+            kettle,
+            faults,
+            stop,
+            thread: Some(thread),
+            _slave: slave,
+        })
+    }
+
+    /// Path of the pseudo terminal to open as serial device.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replace the injected faults.
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    /// Current state of the emulated kettle, unaffected by injected faults.
+    pub fn state(&self) -> State {
+        self.kettle.lock().unwrap().state()
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Return true with `probability`, which unlike [`Rng::gen_bool`] does not panic outside zero to
+/// one or on NaN.
+fn chance(rng: &mut impl Rng, probability: f64) -> bool {
+    probability > 0.0 && rng.gen::<f64>() < probability
+}
@@ -0,0 +1,70 @@
+//! Framing of the checksummed Brewslave protocol.
+//!
+//! Every request and response is sent as
+//!
+//! ```text
+//! START | LEN | CODE | PAYLOAD (LEN bytes) | CRC
+//! ```
+//!
+//! where `CODE` is the command for requests and the ACK/NACK status for responses and `CRC` is a
+//! CRC-8 (polynomial 0x07) over `LEN`, `CODE` and `PAYLOAD`.
+
+/// Byte marking the beginning of a frame.
+pub const START: u8 = 0xaa;
+
+/// Largest payload a frame may carry.
+pub const MAX_PAYLOAD: usize = 32;
+
+/// Compute the CRC-8 with polynomial 0x07 and zero initial value of `data`.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Encode `code` and `payload` into a complete frame.
+///
+/// # Panics
+///
+/// Panics if `payload` is longer than [`MAX_PAYLOAD`].
+pub fn encode(code: u8, payload: &[u8]) -> Vec<u8> {
+    assert!(payload.len() <= MAX_PAYLOAD, "frame payload too large");
+
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.push(START);
+    frame.push(payload.len() as u8);
+    frame.push(code);
+    frame.extend_from_slice(payload);
+    frame.push(crc8(&frame[1..]));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc8_check_value() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+    }
+
+    #[test]
+    fn encode_layout() {
+        let frame = encode(0x02, &[0x10, 0x20]);
+
+        assert_eq!(frame[..5], [START, 2, 0x02, 0x10, 0x20]);
+        assert_eq!(frame[5], crc8(&[2, 0x02, 0x10, 0x20]));
+    }
+
+    #[test]
+    #[should_panic(expected = "frame payload too large")]
+    fn encode_rejects_large_payload() {
+        encode(0x01, &[0; MAX_PAYLOAD + 1]);
+    }
+}
@@ -1,12 +1,17 @@
 //! Serial communication with the Brewslave.
 
 use byteorder::{ByteOrder, LittleEndian};
+use serde::Deserialize;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::sync::RwLock;
 use tokio::time::{timeout, Duration};
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_serial::{ClearBuffer, SerialPort, SerialPortBuilderExt, SerialStream};
+
+#[cfg(all(unix, feature = "emulator"))]
+pub mod emulator;
+pub mod frame;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -14,6 +19,8 @@ pub enum Error {
     Nack,
     #[error("Received unexpected data")]
     UnexpectedData,
+    #[error("Frame checksum mismatch")]
+    Checksum,
     #[error("Tokio I/O error")]
     TokioIo(#[from] tokio::io::Error),
     #[error("Tokio Serial error")]
@@ -22,10 +29,23 @@ pub enum Error {
     Timeout(#[from] tokio::time::error::Elapsed),
 }
 
+/// Wire format spoken by the Brewslave firmware.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Raw command bytes with fixed size replies as understood by old firmware.
+    #[default]
+    Legacy,
+    /// Checksummed frames as described in [`frame`].
+    Framed,
+}
+
 /// Serial communication structure wrapping the Brewslave protocol.
 #[derive(Debug)]
 pub struct Comm {
     stream: Arc<RwLock<SerialStream>>,
+    protocol: Protocol,
+    info: Info,
 }
 
 /// Current state of the Brewslave.
@@ -37,16 +57,64 @@ pub struct State {
     pub heater_on: bool,
 }
 
+/// Firmware identification and capabilities of the Brewslave.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Info {
+    /// Firmware version as major, minor, patch or `None` if the firmware does not identify itself.
+    pub firmware_version: Option<(u8, u8, u8)>,
+    /// Protocol version, zero for firmware predating identification.
+    pub protocol_version: u8,
+    /// Whether the stirrer can be switched.
+    pub stirrer: bool,
+    /// Whether the heater follows the target temperature.
+    pub heater: bool,
+    /// Whether the firmware turns the heater off when heartbeats stop.
+    pub watchdog: bool,
+    /// Whether the heater can be driven with a duty cycle instead of a target temperature.
+    pub heater_duty: bool,
+    /// Number of temperature sensors.
+    pub sensors: u8,
+}
+
+impl Info {
+    /// Capabilities assumed for firmware that does not answer the identify command.
+    pub fn legacy() -> Self {
+        Self {
+            firmware_version: None,
+            protocol_version: 0,
+            stirrer: true,
+            heater: true,
+            watchdog: false,
+            heater_duty: false,
+            sensors: 1,
+        }
+    }
+}
+
+const STATE_LENGTH: usize = 9;
+const INFO_LENGTH: usize = 6;
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of bytes skipped while looking for the start of a frame before giving up.
+const MAX_GARBAGE: usize = 64;
+
 const RESPONSE_ACK: u8 = 0x80;
 const RESPONSE_NACK: u8 = 0x40;
 const RESPONSE_STIRRER_BIT: u8 = 0x1;
 const RESPONSE_HEATER_BIT: u8 = 0x2;
+const CAPABILITY_STIRRER_BIT: u8 = 0x1;
+const CAPABILITY_HEATER_BIT: u8 = 0x2;
+const CAPABILITY_WATCHDOG_BIT: u8 = 0x4;
+const CAPABILITY_HEATER_DUTY_BIT: u8 = 0x8;
 
 enum Command {
     ReadState = 0x1,
     SetTemperature = 0x2,
     TurnStirrerOn = 0x3,
     TurnStirrerOff = 0x4,
+    Identify = 0x5,
+    Heartbeat = 0x6,
+    SetHeaterDuty = 0x7,
 }
 
 fn ack_byte_to(ack: u8) -> Result<(), Error> {
@@ -59,11 +127,95 @@ fn ack_byte_to(ack: u8) -> Result<(), Error> {
     }
 }
 
+/// Decode the state reply `data` consisting of current and target temperature and state bits.
+fn decode_state(data: &[u8]) -> Result<State, Error> {
+    if data.len() != STATE_LENGTH {
+        return Err(Error::UnexpectedData);
+    }
+
+    let current_temperature = LittleEndian::read_f32(&data[0..4]);
+    let target_temperature = LittleEndian::read_f32(&data[4..8]);
+
+    let current_temperature = if current_temperature.is_nan() {
+        None
+    } else {
+        Some(current_temperature)
+    };
+
+    let target_temperature = if target_temperature.is_nan() {
+        None
+    } else {
+        Some(target_temperature)
+    };
+
+    Ok(State {
+        current_temperature,
+        target_temperature,
+        stirrer_on: (data[8] & RESPONSE_STIRRER_BIT) != 0,
+        heater_on: (data[8] & RESPONSE_HEATER_BIT) != 0,
+    })
+}
+
+/// Decode the identify reply `data`.
+fn decode_info(data: &[u8]) -> Result<Info, Error> {
+    if data.len() != INFO_LENGTH {
+        return Err(Error::UnexpectedData);
+    }
+
+    Ok(Info {
+        firmware_version: Some((data[0], data[1], data[2])),
+        protocol_version: data[3],
+        stirrer: (data[4] & CAPABILITY_STIRRER_BIT) != 0,
+        heater: (data[4] & CAPABILITY_HEATER_BIT) != 0,
+        watchdog: (data[4] & CAPABILITY_WATCHDOG_BIT) != 0,
+        heater_duty: (data[4] & CAPABILITY_HEATER_DUTY_BIT) != 0,
+        sensors: data[5],
+    })
+}
+
+/// Skip garbage up to the next start byte and read a single response frame from `stream`,
+/// returning status code and payload.
+async fn read_frame<R: AsyncRead + Unpin>(stream: &mut R) -> Result<(u8, Vec<u8>), Error> {
+    let mut skipped = 0;
+
+    while stream.read_u8().await? != frame::START {
+        skipped += 1;
+
+        if skipped > MAX_GARBAGE {
+            return Err(Error::UnexpectedData);
+        }
+    }
+
+    let length = stream.read_u8().await? as usize;
+
+    if length > frame::MAX_PAYLOAD {
+        return Err(Error::UnexpectedData);
+    }
+
+    let mut data = vec![0; length + 3];
+    data[0] = length as u8;
+    stream.read_exact(&mut data[1..]).await?;
+
+    let crc = data.pop().ok_or(Error::UnexpectedData)?;
+
+    if frame::crc8(&data) != crc {
+        return Err(Error::Checksum);
+    }
+
+    Ok((data[1], data.split_off(2)))
+}
+
 impl Comm {
-    /// Create a new communication structure.
+    /// Create a new communication structure speaking the legacy protocol.
     ///
     /// As of now, it tries to open `/dev/tty/ACM0`.
-    pub fn new(path: &Path) -> Result<Self, Error> {
+    pub async fn new(path: &Path) -> Result<Self, Error> {
+        Self::with_protocol(path, Protocol::Legacy).await
+    }
+
+    /// Create a new communication structure speaking `protocol` and identify the firmware. If the
+    /// firmware does not answer, [`Info::legacy`] capabilities are assumed.
+    pub async fn with_protocol(path: &Path, protocol: Protocol) -> Result<Self, Error> {
         let stream = tokio_serial::new(path.to_string_lossy(), 115200)
             .flow_control(tokio_serial::FlowControl::None)
             .data_bits(tokio_serial::DataBits::Eight)
@@ -71,66 +223,204 @@ impl Comm {
             .stop_bits(tokio_serial::StopBits::One)
             .open_native_async()?;
 
-        Ok(Self {
+        let mut comm = Self {
             stream: Arc::new(RwLock::new(stream)),
-        })
+            protocol,
+            info: Info::legacy(),
+        };
+
+        match comm.identify().await {
+            Ok(info) => comm.info = info,
+            Err(Error::Timeout(_) | Error::Nack) => {
+                comm.stream.write().await.clear(ClearBuffer::Input)?;
+            }
+            Err(err) => return Err(err),
+        }
+
+        Ok(comm)
     }
 
-    /// Read the current state comprised of temperature and device states.
-    pub async fn read_state(&self) -> Result<State, Error> {
-        let mut stream = self.stream.write().await;
-        stream.write_u8(Command::ReadState as u8).await?;
+    /// Protocol spoken with the Brewslave.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
 
-        let mut current: [u8; 4] = [0; 4];
-        let mut target: [u8; 4] = [0; 4];
-        let mut state: [u8; 1] = [0; 1];
+    /// Firmware identification obtained when the connection was opened.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
 
-        timeout(Duration::from_secs(1), stream.read_exact(&mut current)).await??;
-        timeout(Duration::from_secs(1), stream.read_exact(&mut target)).await??;
-        timeout(Duration::from_secs(1), stream.read_exact(&mut state)).await??;
+    /// Ask the firmware for its version and capabilities.
+    pub async fn identify(&self) -> Result<Info, Error> {
+        match self.protocol {
+            Protocol::Legacy => {
+                let mut stream = self.stream.write().await;
+                stream.write_u8(Command::Identify as u8).await?;
 
-        let current_temperature = LittleEndian::read_f32(&current);
-        let target_temperature = LittleEndian::read_f32(&target);
+                let mut data = [0; INFO_LENGTH];
+                timeout(TIMEOUT, stream.read_exact(&mut data)).await??;
+                decode_info(&data)
+            }
+            Protocol::Framed => decode_info(&self.request(Command::Identify, &[]).await?),
+        }
+    }
 
-        let current_temperature = if current_temperature.is_nan() {
-            None
-        } else {
-            Some(current_temperature)
-        };
+    /// Send a framed `command` with `payload` and return the payload of an acknowledged response.
+    ///
+    /// Stale input is discarded before sending, so that a previously garbled reply cannot
+    /// desynchronise this one.
+    async fn request(&self, command: Command, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut stream = self.stream.write().await;
+        stream.clear(ClearBuffer::Input)?;
+        stream
+            .write_all(&frame::encode(command as u8, payload))
+            .await?;
 
-        let target_temperature = if target_temperature.is_nan() {
-            None
-        } else {
-            Some(target_temperature)
-        };
+        let (status, payload) = timeout(TIMEOUT, read_frame(&mut *stream)).await??;
+        ack_byte_to(status)?;
+        Ok(payload)
+    }
 
-        Ok(State {
-            current_temperature,
-            target_temperature,
-            stirrer_on: (state[0] & RESPONSE_STIRRER_BIT) != 0,
-            heater_on: (state[0] & RESPONSE_HEATER_BIT) != 0,
-        })
+    /// Send a legacy `command` and wait for the ACK byte.
+    async fn legacy_request(&self, command: &[u8]) -> Result<(), Error> {
+        let mut stream = self.stream.write().await;
+        stream.write_all(command).await?;
+        ack_byte_to(timeout(TIMEOUT, stream.read_u8()).await??)
+    }
+
+    /// Read the current state comprised of temperature and device states.
+    pub async fn read_state(&self) -> Result<State, Error> {
+        match self.protocol {
+            Protocol::Legacy => {
+                let mut stream = self.stream.write().await;
+                stream.write_u8(Command::ReadState as u8).await?;
+
+                let mut data = [0; STATE_LENGTH];
+                timeout(TIMEOUT, stream.read_exact(&mut data)).await??;
+                decode_state(&data)
+            }
+            Protocol::Framed => decode_state(&self.request(Command::ReadState, &[]).await?),
+        }
     }
 
     /// Write a new target temperature in degree Celsius the Brewslave is supposed to reach.
     pub async fn set_temperature(&self, temperature: f32) -> Result<(), Error> {
-        let mut command = vec![Command::SetTemperature as u8, 0, 0, 0, 0];
-        LittleEndian::write_f32(&mut command[1..], temperature);
+        let mut payload = [0; 4];
+        LittleEndian::write_f32(&mut payload, temperature);
 
-        let mut stream = self.stream.write().await;
-        stream.write_all(&command).await?;
-        ack_byte_to(stream.read_u8().await?)
+        match self.protocol {
+            Protocol::Legacy => {
+                let mut command = vec![Command::SetTemperature as u8];
+                command.extend_from_slice(&payload);
+                self.legacy_request(&command).await
+            }
+            Protocol::Framed => {
+                self.request(Command::SetTemperature, &payload).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Keep the firmware watchdog alive. Unless the next heartbeat arrives within `timeout`, the
+    /// firmware turns the heater off. The timeout is sent in whole seconds.
+    pub async fn heartbeat(&self, timeout: Duration) -> Result<(), Error> {
+        let mut payload = [0; 2];
+        LittleEndian::write_u16(&mut payload, timeout.as_secs().min(u16::MAX as u64) as u16);
+
+        match self.protocol {
+            Protocol::Legacy => {
+                let mut command = vec![Command::Heartbeat as u8];
+                command.extend_from_slice(&payload);
+                self.legacy_request(&command).await
+            }
+            Protocol::Framed => {
+                self.request(Command::Heartbeat, &payload).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Drive the heater with `duty` between zero and one, sent as a single byte. The duty
+    /// overrides the thermostat until a new target temperature is set or the watchdog expires.
+    pub async fn set_heater_duty(&self, duty: f32) -> Result<(), Error> {
+        let payload = [(duty.clamp(0.0, 1.0) * 255.0).round() as u8];
+
+        match self.protocol {
+            Protocol::Legacy => {
+                self.legacy_request(&[Command::SetHeaterDuty as u8, payload[0]])
+                    .await
+            }
+            Protocol::Framed => {
+                self.request(Command::SetHeaterDuty, &payload).await?;
+                Ok(())
+            }
+        }
     }
 
     /// Write new stirrer state.
     pub async fn write_stirrer(&self, stirrer_on: bool) -> Result<(), Error> {
         let command = match stirrer_on {
-            true => Command::TurnStirrerOn as u8,
-            false => Command::TurnStirrerOff as u8,
+            true => Command::TurnStirrerOn,
+            false => Command::TurnStirrerOff,
         };
 
-        let mut stream = self.stream.write().await;
-        stream.write_u8(command).await?;
-        ack_byte_to(stream.read_u8().await?)
+        match self.protocol {
+            Protocol::Legacy => self.legacy_request(&[command as u8]).await,
+            Protocol::Framed => {
+                self.request(command, &[]).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frame_round_trip() {
+        let encoded = frame::encode(RESPONSE_ACK, &[1, 2, 3]);
+        let (code, payload) = read_frame(&mut encoded.as_slice()).await.unwrap();
+
+        assert_eq!(code, RESPONSE_ACK);
+        assert_eq!(payload, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn corrupted_crc_is_rejected() {
+        let mut encoded = frame::encode(RESPONSE_ACK, &[1, 2, 3]);
+        *encoded.last_mut().unwrap() ^= 0xff;
+
+        let result = read_frame(&mut encoded.as_slice()).await;
+        assert!(matches!(result, Err(Error::Checksum)));
+    }
+
+    #[tokio::test]
+    async fn leading_garbage_is_skipped() {
+        let mut data = vec![0x00, 0x13, 0x37];
+        data.extend(frame::encode(RESPONSE_NACK, &[]));
+
+        let (code, payload) = read_frame(&mut data.as_slice()).await.unwrap();
+
+        assert_eq!(code, RESPONSE_NACK);
+        assert!(payload.is_empty());
+    }
+
+    #[tokio::test]
+    async fn too_much_garbage_is_rejected() {
+        let mut data = vec![0; MAX_GARBAGE + 1];
+        data.extend(frame::encode(RESPONSE_ACK, &[]));
+
+        let result = read_frame(&mut data.as_slice()).await;
+        assert!(matches!(result, Err(Error::UnexpectedData)));
+    }
+
+    #[tokio::test]
+    async fn oversized_length_is_rejected() {
+        let data = [frame::START, frame::MAX_PAYLOAD as u8 + 1, RESPONSE_ACK];
+
+        let result = read_frame(&mut data.as_slice()).await;
+        assert!(matches!(result, Err(Error::UnexpectedData)));
     }
 }
@@ -0,0 +1,100 @@
+//! Exercise the serial protocols against the emulated Brewslave.
+
+#![cfg(unix)]
+
+use comm::emulator::{Config, Emulator, Faults};
+use comm::{Comm, Error, Protocol};
+
+const PROTOCOLS: [Protocol; 2] = [Protocol::Legacy, Protocol::Framed];
+
+async fn connect(protocol: Protocol) -> (Emulator, Comm) {
+    let emulator = Emulator::spawn(Config {
+        protocol,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let comm = Comm::with_protocol(emulator.path(), protocol)
+        .await
+        .unwrap();
+    (emulator, comm)
+}
+
+#[tokio::test]
+async fn set_and_read_state() {
+    for protocol in PROTOCOLS {
+        let (_emulator, comm) = connect(protocol).await;
+
+        assert!(comm.info().firmware_version.is_some());
+
+        comm.set_temperature(55.0).await.unwrap();
+        comm.write_stirrer(true).await.unwrap();
+
+        let state = comm.read_state().await.unwrap();
+        assert_eq!(state.target_temperature, Some(55.0));
+        assert!(state.current_temperature.is_some());
+        assert!(state.stirrer_on);
+        assert!(state.heater_on);
+
+        comm.write_stirrer(false).await.unwrap();
+        assert!(!comm.read_state().await.unwrap().stirrer_on);
+    }
+}
+
+#[tokio::test]
+async fn nack_is_reported() {
+    for protocol in PROTOCOLS {
+        let (emulator, comm) = connect(protocol).await;
+
+        // Probabilities above one are clamped instead of panicking in the emulator.
+        emulator.set_faults(Faults {
+            nack: 5.0,
+            ..Default::default()
+        });
+
+        let result = comm.set_temperature(55.0).await;
+        assert!(
+            matches!(result, Err(Error::Nack)),
+            "{protocol:?}: {result:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn failing_sensor_reads_none() {
+    for protocol in PROTOCOLS {
+        let (emulator, comm) = connect(protocol).await;
+
+        emulator.set_faults(Faults {
+            nan_sensor: true,
+            ..Default::default()
+        });
+
+        let state = comm.read_state().await.unwrap();
+        assert_eq!(state.current_temperature, None);
+    }
+}
+
+#[tokio::test]
+async fn recovers_after_dropped_bytes() {
+    for protocol in PROTOCOLS {
+        let (emulator, comm) = connect(protocol).await;
+
+        emulator.set_faults(Faults {
+            drop_byte: 1.0,
+            ..Default::default()
+        });
+
+        let result = comm.read_state().await;
+        assert!(
+            matches!(result, Err(Error::Timeout(_))),
+            "{protocol:?}: {result:?}"
+        );
+
+        emulator.set_faults(Faults::default());
+        comm.set_temperature(60.0).await.unwrap();
+
+        let state = comm.read_state().await.unwrap();
+        assert_eq!(state.target_temperature, Some(60.0));
+    }
+}
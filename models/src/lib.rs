@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::convert::From;
 use std::fmt::Display;
 use std::str::FromStr;
@@ -15,11 +15,264 @@ pub struct Device {
     pub serial_problem: bool,
 }
 
-/// Recipe step.
+/// Health of the connection to the device.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DeviceHealth {
+    /// `false` while the connection is broken and being re-established.
+    pub connected: bool,
+    /// Number of failed operations since the last success.
+    pub consecutive_errors: u32,
+    /// Number of successful reconnects since the server started.
+    pub reconnects: u64,
+    /// Most recent error message.
+    pub last_error: Option<String>,
+    /// State of the heartbeat keeping the device watchdog alive.
+    pub watchdog: Watchdog,
+    /// State of the host-side heater control loop.
+    #[serde(default)]
+    pub heater_control: HeaterControl,
+}
+
+/// Heartbeat state of the device watchdog.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Watchdog {
+    /// Whether heartbeats are sent, i.e. the watchdog is configured and supported by the device.
+    pub active: bool,
+    /// Time without heartbeat after which the device turns the heater off.
+    pub timeout: std::time::Duration,
+    /// Time since the last acknowledged heartbeat.
+    pub last_heartbeat: Option<std::time::Duration>,
+    /// Whether the last acknowledged heartbeat is older than the timeout, so that the device has
+    /// turned the heater off.
+    pub expired: bool,
+}
+
+/// State of the host-side heater control loop.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct HeaterControl {
+    /// Whether the heater duty is computed by the server instead of the device thermostat.
+    pub active: bool,
+    /// Temperature the loop controls to.
+    pub setpoint: Option<f32>,
+    /// Most recently applied heater duty between zero and one.
+    pub duty: Option<f32>,
+}
+
+/// Firmware identification and capabilities of the device.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DeviceInfo {
+    /// Firmware version or `None` if the firmware does not identify itself.
+    pub firmware_version: Option<String>,
+    /// Protocol version, zero for firmware predating identification.
+    pub protocol_version: u8,
+    /// Whether the stirrer can be switched.
+    pub stirrer: bool,
+    /// Whether the heater follows the target temperature.
+    pub heater: bool,
+    /// Whether the device turns the heater off when heartbeats stop.
+    #[serde(default)]
+    pub watchdog: bool,
+    /// Whether the heater can be driven with a duty cycle.
+    #[serde(default)]
+    pub heater_duty: bool,
+    /// Number of temperature sensors.
+    pub sensors: u8,
+}
+
+/// Ingredient added during a step, e.g. a hop addition during the boil.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Addition {
+    pub ingredient: String,
+    /// Amount including the unit, e.g. "25 g".
+    pub amount: String,
+    /// Time before the end of the step at which the ingredient is added.
+    pub before_end: std::time::Duration,
+}
+
+/// Reaction to the temperature leaving the tolerance band while holding.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfBand {
+    /// Keep the hold clock running and record the deviation.
+    Flag,
+    /// Stop the hold clock until the temperature is back in band and record the deviation.
+    Pause,
+}
+
+/// Tolerance of a step overriding the configured defaults, `None` keeps the default.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Tolerance {
+    /// Allowed deviation from the target temperature in Kelvin.
+    pub band: Option<f32>,
+    /// Number of consecutive in-band samples before the hold starts.
+    pub settle_samples: Option<u32>,
+    pub out_of_band: Option<OutOfBand>,
+}
+
+/// Heat to a target temperature and hold it for a duration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct Step {
+pub struct HoldStep {
     pub target_temperature: f32,
     pub duration: std::time::Duration,
+    /// Run the stirrer while executing this step.
+    #[serde(default)]
+    pub stirrer_on: bool,
+    /// Ingredients added while holding.
+    #[serde(default)]
+    pub additions: Vec<Addition>,
+    #[serde(default)]
+    pub tolerance: Tolerance,
+}
+
+/// Move the target linearly from the current to the target temperature.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RampStep {
+    pub target_temperature: f32,
+    /// Rate of change in °C per minute.
+    pub rate: f32,
+    /// Run the stirrer while executing this step.
+    #[serde(default)]
+    pub stirrer_on: bool,
+}
+
+/// Heat until the wort boils and keep it boiling for a duration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BoilStep {
+    pub duration: std::time::Duration,
+    /// Run the stirrer while executing this step.
+    #[serde(default)]
+    pub stirrer_on: bool,
+    /// Ingredients added while boiling.
+    #[serde(default)]
+    pub additions: Vec<Addition>,
+    #[serde(default)]
+    pub tolerance: Tolerance,
+}
+
+/// Keep the current temperature until the user confirms `message`, e.g. "add grain".
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfirmStep {
+    pub message: String,
+    /// Run the stirrer while executing this step.
+    #[serde(default)]
+    pub stirrer_on: bool,
+}
+
+/// Keep the current temperature for a duration and then notify the user with `message`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ReminderStep {
+    pub message: String,
+    pub duration: std::time::Duration,
+    /// Run the stirrer while executing this step.
+    #[serde(default)]
+    pub stirrer_on: bool,
+}
+
+/// Recipe step. Steps without a `type` are hold steps, as stored before step types existed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", from = "SerializedStep")]
+pub enum Step {
+    Hold(HoldStep),
+    Ramp(RampStep),
+    Boil(BoilStep),
+    Confirm(ConfirmStep),
+    Reminder(ReminderStep),
+}
+
+impl Step {
+    /// Temperature the step heats to or `None` if it keeps the current one or boils.
+    pub fn target_temperature(&self) -> Option<f32> {
+        match self {
+            Step::Hold(step) => Some(step.target_temperature),
+            Step::Ramp(step) => Some(step.target_temperature),
+            Step::Boil(_) | Step::Confirm(_) | Step::Reminder(_) => None,
+        }
+    }
+
+    /// Timed part of the step, zero if the step ends as soon as its condition is met.
+    pub fn duration(&self) -> std::time::Duration {
+        match self {
+            Step::Hold(step) => step.duration,
+            Step::Boil(step) => step.duration,
+            Step::Reminder(step) => step.duration,
+            Step::Ramp(_) | Step::Confirm(_) => std::time::Duration::ZERO,
+        }
+    }
+
+    /// Ingredients added during the timed part of the step.
+    pub fn additions(&self) -> &[Addition] {
+        match self {
+            Step::Hold(step) => &step.additions,
+            Step::Boil(step) => &step.additions,
+            Step::Ramp(_) | Step::Confirm(_) | Step::Reminder(_) => &[],
+        }
+    }
+
+    /// Tolerance overrides of steps holding a temperature.
+    pub fn tolerance(&self) -> Option<&Tolerance> {
+        match self {
+            Step::Hold(step) => Some(&step.tolerance),
+            Step::Boil(step) => Some(&step.tolerance),
+            Step::Ramp(_) | Step::Confirm(_) | Step::Reminder(_) => None,
+        }
+    }
+
+    pub fn stirrer_on(&self) -> bool {
+        match self {
+            Step::Hold(step) => step.stirrer_on,
+            Step::Ramp(step) => step.stirrer_on,
+            Step::Boil(step) => step.stirrer_on,
+            Step::Confirm(step) => step.stirrer_on,
+            Step::Reminder(step) => step.stirrer_on,
+        }
+    }
+}
+
+/// Tagged step as written by current clients.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TaggedStep {
+    Hold(HoldStep),
+    Ramp(RampStep),
+    Boil(BoilStep),
+    Confirm(ConfirmStep),
+    Reminder(ReminderStep),
+}
+
+/// Accepted step representation. Only objects without `type` are read as hold steps, so that an
+/// unknown type or invalid fields of a tagged step are rejected instead of read as hold step.
+struct SerializedStep(Step);
+
+impl<'de> Deserialize<'de> for SerializedStep {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        let step = if value.get("type").is_some() {
+            TaggedStep::deserialize(value).map(Step::from)
+        } else {
+            HoldStep::deserialize(value).map(Step::Hold)
+        };
+
+        step.map(Self).map_err(de::Error::custom)
+    }
+}
+
+impl From<TaggedStep> for Step {
+    fn from(step: TaggedStep) -> Self {
+        match step {
+            TaggedStep::Hold(step) => Step::Hold(step),
+            TaggedStep::Ramp(step) => Step::Ramp(step),
+            TaggedStep::Boil(step) => Step::Boil(step),
+            TaggedStep::Confirm(step) => Step::Confirm(step),
+            TaggedStep::Reminder(step) => Step::Reminder(step),
+        }
+    }
+}
+
+impl From<SerializedStep> for Step {
+    fn from(step: SerializedStep) -> Self {
+        step.0
+    }
 }
 
 /// Recipe identifier newtype.
@@ -70,6 +323,20 @@ pub struct NewRecipe {
     pub steps: Vec<Step>,
 }
 
+/// Violation of a single field of a request.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FieldError {
+    /// Path to the offending field, e.g. `steps[1].duration`.
+    pub field: String,
+    pub message: String,
+}
+
+/// All violations found when validating a request.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
 /// Result identifier of the new recipe.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NewRecipeResponse {
@@ -86,6 +353,20 @@ pub struct NewBrew {
 #[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct BrewId(i64);
 
+impl Display for BrewId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for BrewId {
+    type Err = <i64 as FromStr>::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<i64>().map(Self)
+    }
+}
+
 impl From<i64> for BrewId {
     fn from(id: i64) -> Self {
         Self(id)
@@ -104,14 +385,311 @@ pub struct NewBrewResponse {
     pub id: BrewId,
 }
 
+/// Lifecycle state of a brew.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrewState {
+    Running,
+    Completed,
+    Failed,
+    Aborted,
+    /// The server stopped while the brew was running, it can be recovered.
+    Interrupted,
+}
+
+/// A past or running brew.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Brew {
+    pub id: BrewId,
+    pub recipe_id: RecipeId,
+    /// Start time in seconds since the Unix epoch.
+    pub started_at: Option<i64>,
+    /// End time in seconds since the Unix epoch or `None` if still running.
+    pub finished_at: Option<i64>,
+    pub state: BrewState,
+    /// Error message if the brew failed.
+    pub error: Option<String>,
+    /// Last persisted progress or `None` if no step was started.
+    #[serde(default)]
+    pub progress: Option<BrewProgress>,
+}
+
+/// Persisted progress of a brew, used to recover it after an interruption.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BrewProgress {
+    /// Index of the current step.
+    pub step: usize,
+    pub phase: Phase,
+    /// Time the current step was already held at its target temperature.
+    pub held: std::time::Duration,
+}
+
+/// How to continue an interrupted brew.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    /// Continue the current step, keeping the time already held.
+    Resume,
+    /// Run the current step again from the start.
+    RestartStep,
+    /// Mark the brew aborted.
+    Abort,
+}
+
+/// Request to recover an interrupted brew.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Recovery {
+    pub action: RecoveryAction,
+}
+
+/// Multiple brews.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Brews {
+    pub brews: Vec<Brew>,
+}
+
+/// Brew temperature at a point in time.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Sample {
+    /// Time in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub temperature: f32,
+}
+
+/// Multiple samples ordered by time.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Samples {
+    pub samples: Vec<Sample>,
+}
+
+/// Recorded boundaries of a step executed during a brew. Times are in seconds since the Unix
+/// epoch.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StepRecord {
+    pub position: usize,
+    /// Time heating towards the target temperature started.
+    pub started_at: Option<i64>,
+    /// Time the target temperature was reached and the hold started.
+    pub reached_at: Option<i64>,
+    /// Time the hold ended.
+    pub finished_at: Option<i64>,
+}
+
+/// Recorded alert and acknowledgement of an addition during a brew. Times are in seconds since the
+/// Unix epoch.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AdditionRecord {
+    /// Index of the step the addition belongs to.
+    pub step: usize,
+    /// Index of the addition within the step.
+    pub position: usize,
+    /// Time the addition was due and announced.
+    pub alerted_at: Option<i64>,
+    /// Time the user acknowledged adding the ingredient.
+    pub acknowledged_at: Option<i64>,
+}
+
+/// Recorded excursion of the temperature out of the tolerance band while holding a step. Times are
+/// in seconds since the Unix epoch.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Deviation {
+    pub step: usize,
+    pub started_at: i64,
+    /// Time the temperature was back in band or `None` if it did not return.
+    pub ended_at: Option<i64>,
+    /// Largest distance from the target temperature in Kelvin.
+    pub max_deviation: f32,
+}
+
+/// Single recorded measurement including heater and stirrer state.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Measurement {
+    /// Time in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub temperature: Option<f32>,
+    pub heater_on: Option<bool>,
+    pub stirrer_on: Option<bool>,
+}
+
+/// Complete log of a brew for archival.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BrewLog {
+    pub brew: Brew,
+    pub recipe: Recipe,
+    pub steps: Vec<StepRecord>,
+    #[serde(default)]
+    pub additions: Vec<AdditionRecord>,
+    #[serde(default)]
+    pub deviations: Vec<Deviation>,
+    pub measurements: Vec<Measurement>,
+}
+
+/// Phase of the currently executed brew step.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Waiting for the target temperature to be reached.
+    Heating,
+    /// Holding the target temperature for the step duration.
+    Holding,
+    /// Waiting for the user to confirm the step.
+    Waiting,
+}
+
+/// Status of the currently running brew.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BrewStatus {
+    pub id: BrewId,
+    /// Index of the current step.
+    pub step: usize,
+    pub phase: Phase,
+    pub paused: bool,
+    /// Time the current step has been held at its target temperature.
+    pub held: std::time::Duration,
+    /// Estimated remaining hold time of the whole schedule, excluding heating.
+    pub remaining: std::time::Duration,
+    /// Whether the temperature is outside the tolerance band of the hold.
+    #[serde(default)]
+    pub out_of_band: bool,
+}
+
 /// Multiple recipes.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Recipes {
     pub recipes: Vec<Recipe>,
 }
 
+/// Request to turn the heater off by setting the safe temperature on the device
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HeaterOff {
+    /// Change the device even if a brew is ongoing.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// A new stirrer state to set on the device
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Stirrer {
+    pub stirrer_on: bool,
+    /// Change the device even if a brew is ongoing.
+    #[serde(default)]
+    pub force: bool,
+}
+
 /// A new target temperature to set on the device
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TargetTemperature {
     pub target_temperature: f32,
+    /// Change the device even if a brew is ongoing.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Events pushed to subscribers of the event stream.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Current device state snapshot.
+    Device(Device),
+    /// A brew step started heating towards `target_temperature` or `None` if the step keeps the
+    /// current temperature or boils.
+    StepStarted {
+        id: BrewId,
+        step: usize,
+        target_temperature: Option<f32>,
+    },
+    /// The target temperature of a brew step was reached and the hold starts.
+    TargetReached {
+        id: BrewId,
+        step: usize,
+        temperature: f32,
+    },
+    /// A brew step waits until the user confirms `message`.
+    ConfirmationRequired {
+        id: BrewId,
+        step: usize,
+        message: String,
+    },
+    /// The duration of a reminder step passed.
+    Reminder {
+        id: BrewId,
+        step: usize,
+        message: String,
+    },
+    /// Addition `addition` of a brew step is due now.
+    AdditionDue {
+        id: BrewId,
+        step: usize,
+        addition: usize,
+        ingredient: String,
+        amount: String,
+    },
+    /// The temperature left the tolerance band while holding a brew step.
+    OutOfBand {
+        id: BrewId,
+        step: usize,
+        temperature: f32,
+    },
+    /// The temperature returned into the tolerance band.
+    BackInBand {
+        id: BrewId,
+        step: usize,
+        temperature: f32,
+    },
+    /// All steps of a brew were executed.
+    BrewFinished { id: BrewId },
+    /// A safety limit was violated and the brew was stopped.
+    Alarm { id: BrewId, reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn untyped_step_is_hold_step() {
+        let step: Step = serde_json::from_value(json!({
+            "target_temperature": 65.0,
+            "duration": {"secs": 3600, "nanos": 0},
+        }))
+        .unwrap();
+
+        assert!(matches!(step, Step::Hold(step) if step.target_temperature == 65.0));
+    }
+
+    #[test]
+    fn tagged_step_round_trips() {
+        let step = Step::Ramp(RampStep {
+            target_temperature: 72.0,
+            rate: 1.0,
+            stirrer_on: true,
+        });
+
+        let value = serde_json::to_value(&step).unwrap();
+        assert_eq!(value["type"], "ramp");
+        assert_eq!(serde_json::from_value::<Step>(value).unwrap(), step);
+    }
+
+    #[test]
+    fn unknown_type_is_rejected() {
+        let result = serde_json::from_value::<Step>(json!({
+            "type": "hodl",
+            "target_temperature": 65.0,
+            "duration": {"secs": 3600, "nanos": 0},
+        }));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_tagged_step_is_rejected() {
+        let result = serde_json::from_value::<Step>(json!({
+            "type": "ramp",
+            "target_temperature": 65.0,
+            "duration": {"secs": 3600, "nanos": 0},
+        }));
+
+        assert!(result.is_err());
+    }
 }